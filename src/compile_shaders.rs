@@ -1,6 +1,6 @@
 use gl::types::*;
 
-use std::{ffi::CString, io::{Error, ErrorKind}, ptr};
+use std::{ffi::CString, io::Error, ptr};
 
 
 pub fn compile_shaders() -> Result<GLuint, Error> {
@@ -16,7 +16,7 @@ pub fn compile_shaders() -> Result<GLuint, Error> {
         gl::CompileShader(vertex_shader_id);
         match check_shader_compilation(vertex_shader_id) {
             Ok(()) => {},
-            Err(e) => return Err(Error::new(ErrorKind::Other, e)),
+            Err(e) => return Err(Error::other(e)),
         };
 
         let fragment_shader_id = gl::CreateShader(gl::FRAGMENT_SHADER);
@@ -29,7 +29,7 @@ pub fn compile_shaders() -> Result<GLuint, Error> {
         gl::CompileShader(fragment_shader_id);
         match check_shader_compilation(fragment_shader_id) {
             Ok(()) => {},
-            Err(e) => return Err(Error::new(ErrorKind::Other, e)),
+            Err(e) => return Err(Error::other(e)),
         };
 
         println!("Linking shaders...");
@@ -41,7 +41,7 @@ pub fn compile_shaders() -> Result<GLuint, Error> {
         gl::DeleteShader(fragment_shader_id);
         match check_shader_program_link(shader_program_id) {
             Ok(()) => {},
-            Err(e) => return Err(Error::new(ErrorKind::Other, e)),
+            Err(e) => return Err(Error::other(e)),
         };
         Ok(shader_program_id)
     }
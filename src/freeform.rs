@@ -0,0 +1,234 @@
+use crate::models::vec3::Vec3;
+
+// the basis functions cost the square of the degree at every sample, no real model goes this high
+pub const MAX_DEGREE: usize = 16;
+
+// one direction of a free-form curve or surface: a b-spline of the given degree over its knot vector,
+// evaluated between the two parameters of range.
+// bezier splines are b-splines whose knots are all repeated, see Spline::bezier
+pub struct Spline {
+    pub degree: usize,
+    pub knots: Vec<f32>,
+    pub range: (f32, f32),
+}
+
+impl Spline {
+    // a piecewise bezier whose segments meet at the given parameters: the end knots are repeated
+    // degree + 1 times and the inner ones degree times
+    pub fn bezier(degree: usize, parameters: &[f32], range: (f32, f32)) -> Self {
+        let mut knots = Vec::new();
+        for (i, &parameter) in parameters.iter().enumerate() {
+            let multiplicity = if i == 0 || i == parameters.len() - 1 { degree + 1 } else { degree };
+            knots.extend(std::iter::repeat_n(parameter, multiplicity));
+        }
+        Spline {degree, knots, range}
+    }
+
+    // none if the knot vector is too short or decreasing
+    pub fn control_point_count(&self) -> Option<usize> {
+        if self.degree == 0 || self.knots.windows(2).any(|pair| pair[1] < pair[0]) {
+            return None;
+        }
+        self.knots.len().checked_sub(self.degree + 1).filter(|&count| count > self.degree)
+    }
+
+    // the degree + 1 basis functions that aren't zero at u, along with the index of the first one
+    fn basis_functions(&self, u: f32) -> (usize, Vec<f32>) {
+        let degree = self.degree;
+        let knots = &self.knots;
+        let count = knots.len() - degree - 1;
+        // knot span holding u, the last one is closed so the end of the range is included
+        let mut span = degree;
+        while span < count - 1 && u >= knots[span + 1] {
+            span += 1;
+        }
+
+        let mut basis = vec![0.0; degree + 1];
+        let mut left = vec![0.0; degree + 1];
+        let mut right = vec![0.0; degree + 1];
+        basis[0] = 1.0;
+        for j in 1..=degree {
+            left[j] = u - knots[span + 1 - j];
+            right[j] = knots[span + j] - u;
+            let mut saved = 0.0;
+            for r in 0..j {
+                let denominator = right[r + 1] + left[j - r];
+                let temp = if denominator == 0.0 { 0.0 } else { basis[r] / denominator };
+                basis[r] = saved + right[r + 1] * temp;
+                saved = left[j - r] * temp;
+            }
+            basis[j] = saved;
+        }
+        (span - degree, basis)
+    }
+
+    // resolution steps in every knot span crossed by the range, so curved areas get as many points as flat ones
+    fn sample_parameters(&self, resolution: u32) -> Vec<f32> {
+        let (start, end) = self.range;
+        let (low, high) = if start <= end { (start, end) } else { (end, start) };
+        let mut breaks = vec![low];
+        for &knot in &self.knots {
+            if knot > *breaks.last().unwrap() && knot < high {
+                breaks.push(knot);
+            }
+        }
+        breaks.push(high);
+
+        let resolution = resolution.max(1);
+        let mut parameters = Vec::new();
+        for pair in breaks.windows(2) {
+            for step in 0..resolution {
+                parameters.push(pair[0] + (pair[1] - pair[0]) * step as f32 / resolution as f32);
+            }
+        }
+        parameters.push(high);
+        if start > end {
+            parameters.reverse();
+        }
+        parameters
+    }
+}
+
+// points along a curve, weights of 1 everywhere give a non rational curve
+pub fn tessellate_curve(spline: &Spline, control: &[Vec3], weights: &[f32], resolution: u32) -> Vec<Vec3> {
+    spline.sample_parameters(resolution)
+        .into_iter()
+        .map(|u| {
+            let (first, basis) = spline.basis_functions(u);
+            let terms = basis.iter().enumerate().map(|(i, &b)| (first + i, b));
+            weighted_sum(terms, control, weights)
+        })
+        .collect()
+}
+
+// points of a tessellated surface with their texture coordinates, and the triangles between them
+pub struct SurfaceMesh {
+    pub points: Vec<Vec3>,
+    pub texture_coords: Vec<(f32, f32)>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+// grid of points over a surface, with the control points listed u first.
+// the texture coordinates run from 0 to 1 over the range, and triangles with no area
+// (where a row of control points collapses to a pole) are left out
+pub fn tessellate_surface(u: &Spline, v: &Spline, control: &[Vec3], weights: &[f32], resolution: u32) -> SurfaceMesh {
+    let u_parameters = u.sample_parameters(resolution);
+    let v_parameters = v.sample_parameters(resolution);
+    let u_count = u.knots.len() - u.degree - 1;
+
+    let mut points = Vec::with_capacity(u_parameters.len() * v_parameters.len());
+    let mut texture_coords = Vec::with_capacity(points.capacity());
+    for (j, &t) in v_parameters.iter().enumerate() {
+        let (v_first, v_basis) = v.basis_functions(t);
+        for (i, &s) in u_parameters.iter().enumerate() {
+            let (u_first, u_basis) = u.basis_functions(s);
+            let terms = v_basis.iter().enumerate().flat_map(|(b, &v_value)| {
+                u_basis.iter().enumerate()
+                    .map(move |(a, &u_value)| ((v_first + b) * u_count + u_first + a, u_value * v_value))
+            });
+            points.push(weighted_sum(terms, control, weights));
+            texture_coords.push((i as f32 / (u_parameters.len() - 1) as f32, j as f32 / (v_parameters.len() - 1) as f32));
+        }
+    }
+
+    let row = u_parameters.len();
+    let mut triangles = Vec::new();
+    for j in 0..v_parameters.len() - 1 {
+        for i in 0..row - 1 {
+            let corner = j * row + i;
+            for triangle in [[corner, corner + 1, corner + row + 1], [corner, corner + row + 1, corner + row]] {
+                let [a, b, c] = triangle.map(|index| points[index]);
+                if b.sub(a).cross(c.sub(a)).length() > 0.0 {
+                    triangles.push(triangle);
+                }
+            }
+        }
+    }
+    SurfaceMesh {points, texture_coords, triangles}
+}
+
+// rational combination of the control points, terms are (control point index, basis value)
+fn weighted_sum(terms: impl Iterator<Item = (usize, f32)>, control: &[Vec3], weights: &[f32]) -> Vec3 {
+    let mut point = Vec3::new(0.0, 0.0, 0.0);
+    let mut total = 0.0;
+    for (index, basis) in terms {
+        let weight = basis * weights[index];
+        point = point.add(control[index].scale(weight));
+        total += weight;
+    }
+    if total == 0.0 { point } else { point.scale(1.0 / total) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_partition_of_unity(spline: &Spline) {
+        for step in 0..=40 {
+            let u = spline.range.0 + (spline.range.1 - spline.range.0) * step as f32 / 40.0;
+            let (first, basis) = spline.basis_functions(u);
+            assert_eq!(basis.len(), spline.degree + 1);
+            assert!(first + basis.len() <= spline.control_point_count().unwrap());
+            assert!((basis.iter().sum::<f32>() - 1.0).abs() < 1e-5, "{:?} at {}", basis, u);
+            assert!(basis.iter().all(|&value| value >= -1e-6));
+        }
+    }
+
+    #[test]
+    fn basis_functions_sum_to_one() {
+        for degree in [1, 2, 3, 5] {
+            let knots: Vec<f32> = (0..degree * 2 + 6).map(|knot| knot as f32).collect();
+            let count = knots.len() - degree - 1;
+            let range = (knots[degree], knots[count]);
+            assert_partition_of_unity(&Spline {degree, knots, range});
+            assert_partition_of_unity(&Spline::bezier(degree, &[0.0, 0.5, 2.0], (0.0, 2.0)));
+        }
+    }
+
+    #[test]
+    fn control_point_counts() {
+        assert_eq!(Spline::bezier(3, &[0.0, 1.0], (0.0, 1.0)).control_point_count(), Some(4));
+        // every inner parameter of a bezier adds a segment of degree points
+        assert_eq!(Spline::bezier(3, &[0.0, 1.0, 2.0], (0.0, 2.0)).control_point_count(), Some(7));
+        let spline = |degree: usize, knots: &[f32]| Spline {degree, knots: knots.to_vec(), range: (0.0, 1.0)};
+        assert_eq!(spline(2, &[0.0, 0.0, 0.0, 1.0, 1.0, 1.0]).control_point_count(), Some(3));
+        assert_eq!(spline(2, &[0.0, 0.0, 1.0, 1.0]).control_point_count(), None);
+        assert_eq!(spline(2, &[0.0, 0.0, 1.0, 0.5, 1.0, 1.0]).control_point_count(), None);
+        assert_eq!(spline(0, &[0.0, 1.0]).control_point_count(), None);
+    }
+
+    #[test]
+    fn curves_get_resolution_points_per_span() {
+        let control: Vec<Vec3> = (0..7).map(|i| Vec3::new(i as f32, (i % 2) as f32, 0.0)).collect();
+        let spline = Spline::bezier(3, &[0.0, 1.0, 2.0], (0.0, 2.0));
+        let points = tessellate_curve(&spline, &control, &[1.0; 7], 5);
+        assert_eq!(points.len(), 2 * 5 + 1);
+        // a bezier goes through its end points and through the points where its segments meet
+        for (point, control) in [(points[0], control[0]), (points[5], control[3]), (points[10], control[6])] {
+            assert!(point.sub(control).length() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn rational_curves_follow_the_weights() {
+        // a quarter of the unit circle as a rational quadratic bezier
+        let control = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)];
+        let spline = Spline::bezier(2, &[0.0, 1.0], (0.0, 1.0));
+        let points = tessellate_curve(&spline, &control, &[1.0, std::f32::consts::FRAC_1_SQRT_2, 1.0], 8);
+        assert!(points.iter().all(|point| (point.length() - 1.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn surface_grid_and_poles() {
+        let spline = Spline::bezier(1, &[0.0, 1.0], (0.0, 1.0));
+        let square = [Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 1.0, 0.0)];
+        let mesh = tessellate_surface(&spline, &spline, &square, &[1.0; 4], 4);
+        assert_eq!((mesh.points.len(), mesh.texture_coords.len(), mesh.triangles.len()), (25, 25, 32));
+        assert_eq!((mesh.texture_coords[0], mesh.texture_coords[24]), ((0.0, 0.0), (1.0, 1.0)));
+
+        // the top row collapses to a single point, half of the triangles along it have no area
+        let triangle = [square[0], square[1], square[2], square[2]];
+        let mesh = tessellate_surface(&spline, &spline, &triangle, &[1.0; 4], 4);
+        assert_eq!((mesh.points.len(), mesh.triangles.len()), (25, 28));
+    }
+}
@@ -3,4 +3,9 @@ pub const WIN_HEIGHT: u32 = 600;
 pub const FOV: f32 = 45.0;
 pub const NEAR: f32 = 0.1;
 pub const FAR: f32 = 100.0;
-pub const TRANSFORM_SPEED: f32 = 0.05;
\ No newline at end of file
+pub const TRANSFORM_SPEED: f32 = 0.05;
+pub const CREASE_ANGLE: f32 = 60.0;
+pub const LINE_WIDTH: f32 = 1.0;
+pub const POINT_SIZE: f32 = 4.0;
+pub const TESSELLATION_RESOLUTION: u32 = 8;
+pub const MAX_TESSELLATION_RESOLUTION: u32 = 128;
@@ -0,0 +1,896 @@
+use std::fs;
+use std::path::Path;
+
+use gl::types::GLuint;
+
+use crate::json::{parse_json, JsonValue};
+use crate::mapped_file::MappedFile;
+use crate::models::mat4::Mat4;
+use crate::models::material::{Material, MaterialRange};
+use crate::models::obj_data::{ObjData, Vertex};
+use crate::models::obj_parse_error::{ObjParseError, ObjParseErrorKind};
+use crate::models::parse_options::ParseOptions;
+use crate::models::sub_mesh::SubMesh;
+use crate::models::vec3::Vec3;
+use crate::obj_parser::{finish_sub_meshes, generate_random_color};
+
+const GLB_MAGIC: &[u8] = b"glTF";
+// magic, version and total length
+const GLB_HEADER_SIZE: usize = 12;
+const GLB_CHUNK_JSON: u32 = 0x4E4F534A;
+const GLB_CHUNK_BIN: u32 = 0x004E4942;
+// required extensions that can be honoured: quantization only widens the component types
+// allowed for attributes, which are all read anyway
+const SUPPORTED_EXTENSIONS: [&str; 1] = ["KHR_mesh_quantization"];
+
+const COMPONENT_INT8: usize = 5120;
+const COMPONENT_UINT8: usize = 5121;
+const COMPONENT_INT16: usize = 5122;
+const COMPONENT_UINT16: usize = 5123;
+const COMPONENT_UINT32: usize = 5125;
+const COMPONENT_FLOAT: usize = 5126;
+
+// the elements of an accessor, converted to f64 with normalization and sparse substitution applied
+struct Accessor {
+    values: Vec<f64>,
+    components: usize,
+}
+
+impl Accessor {
+    fn count(&self) -> usize {
+        self.values.len() / self.components
+    }
+
+    fn get(&self, element: usize, component: usize) -> f64 {
+        self.values[element * self.components + component]
+    }
+
+    fn vec3(&self, element: usize) -> Vec3 {
+        Vec3::new(self.get(element, 0) as f32, self.get(element, 1) as f32, self.get(element, 2) as f32)
+    }
+}
+
+// the json tree with the content of its buffers, a buffer that couldn't be loaded in lenient mode is None
+struct Document<'a> {
+    root: &'a JsonValue,
+    buffers: Vec<Option<Vec<u8>>>,
+    directory: &'a Path,
+}
+
+// everything collected from the primitives of the scene, in the layout ObjData wants
+struct MeshBuilder {
+    vertices: Vec<Vertex>,
+    triangles: Vec<GLuint>,
+    triangle_materials: Vec<Option<usize>>,
+    smoothing_groups: Vec<u32>,
+    lines: Vec<GLuint>,
+    points: Vec<GLuint>,
+    sub_meshes: Vec<SubMesh>,
+    has_vertex_colors: bool,
+}
+
+// reads gltf 2.0 files, either json (.gltf) with external or base64 embedded buffers, or the binary
+// container (.glb). the meshes of the default scene are placed with the transforms of their nodes,
+// each node instance becoming a sub-mesh. pbr materials are approximated with the phong parameters
+// of mtl files, and the base colour texture is kept either as a file path or as embedded image bytes
+pub fn parse_gltf_file(file_path: &str, options: &ParseOptions) -> Result<(ObjData, Vec<ObjParseError>), ObjParseError> {
+    let file = MappedFile::open(file_path).map_err(|e| ObjParseError::io(file_path, e))?;
+    let is_glb = file.starts_with(GLB_MAGIC);
+    let (json, bin_chunk) = if is_glb { split_glb(&file, file_path)? } else { (&file[..], None) };
+    let text = std::str::from_utf8(json).map_err(|_| malformed(file_path, "the json is not valid utf-8"))?;
+    let root = parse_json(text).map_err(|(offset, reason)| {
+        // offsets inside a glb can't be shown as a line of the file
+        let (line_number, column) = if is_glb { (0, 0) } else { line_and_column(text, offset) };
+        ObjParseError::new(file_path, line_number, column, "", ObjParseErrorKind::Malformed(reason))
+    })?;
+    check_asset(&root).map_err(|kind| error(file_path, kind))?;
+
+    let mut warnings = Vec::new();
+    let mut dependencies = Vec::new();
+    let directory = Path::new(file_path).parent().unwrap_or(Path::new(""));
+    let buffers = load_buffers(&root, bin_chunk, directory, file_path, options, &mut warnings, &mut dependencies)?;
+    let document = Document {root: &root, buffers, directory};
+
+    let materials = load_materials(&document, file_path, options, &mut warnings)?;
+    let has_vertex_colors = items(&root, "meshes").iter()
+        .flat_map(|mesh| items(mesh, "primitives"))
+        .any(|primitive| primitive.get("attributes").and_then(|attributes| attributes.get("COLOR_0")).is_some());
+    let mut builder = MeshBuilder {
+        vertices: Vec::new(),
+        triangles: Vec::new(),
+        triangle_materials: Vec::new(),
+        smoothing_groups: Vec::new(),
+        lines: Vec::new(),
+        points: Vec::new(),
+        sub_meshes: Vec::new(),
+        has_vertex_colors,
+    };
+    for (object, mesh, transform) in mesh_instances(&root, file_path, options, &mut warnings)? {
+        let Some(mesh) = items(&root, "meshes").get(mesh) else {
+            report(malformed(file_path, "a node refers to a mesh that doesn't exist"), options, &mut warnings)?;
+            continue;
+        };
+        let group = mesh.get("name").and_then(JsonValue::as_str).unwrap_or("");
+        builder.sub_meshes.push(SubMesh::new(&object, group, builder.triangles.len()));
+        for primitive in items(mesh, "primitives") {
+            if let Err(kind) = builder.add_primitive(&document, primitive, &transform, materials.len()) {
+                report(error(file_path, kind), options, &mut warnings)?;
+            }
+        }
+    }
+
+    let MeshBuilder {vertices, triangles, triangle_materials, smoothing_groups, lines, points, sub_meshes, ..} = builder;
+    let num_indices = triangles.len();
+    let mut obj_data = ObjData::new(vertices, triangles, smoothing_groups);
+    obj_data.sub_meshes = finish_sub_meshes(sub_meshes, num_indices);
+    obj_data.material_ranges = get_material_ranges(&triangle_materials);
+    obj_data.materials = materials;
+    obj_data.dependencies = dependencies;
+    obj_data.has_vertex_colors = has_vertex_colors;
+    obj_data.set_lines_and_points(lines, points);
+    Ok((obj_data, warnings))
+}
+
+// a glb container or a json document
+pub fn looks_like_gltf(head: &[u8]) -> bool {
+    head.starts_with(GLB_MAGIC) || head.trim_ascii_start().starts_with(b"{")
+}
+
+// a glb file is a header followed by a json chunk and an optional binary chunk
+fn split_glb<'a>(bytes: &'a [u8], file_path: &str) -> Result<(&'a [u8], Option<&'a [u8]>), ObjParseError> {
+    let word = |offset: usize| bytes.get(offset..offset + 4).map(|word| u32::from_le_bytes(word.try_into().unwrap()) as usize);
+    let (Some(version), Some(length)) = (word(4), word(8)) else {
+        return Err(malformed(file_path, "too short for a glb header"));
+    };
+    if version != 2 {
+        return Err(error(file_path, ObjParseErrorKind::Unsupported(format!("glb version {}", version))));
+    }
+    if length > bytes.len() {
+        return Err(malformed(file_path, "the file is shorter than its glb header says"));
+    }
+
+    let mut chunks = Vec::new();
+    let mut offset = GLB_HEADER_SIZE;
+    while offset + 8 <= length {
+        let (chunk_length, chunk_type) = (word(offset).unwrap(), word(offset + 4).unwrap() as u32);
+        let end = (offset + 8).checked_add(chunk_length).filter(|&end| end <= length)
+            .ok_or_else(|| malformed(file_path, "a glb chunk goes past the end of the file"))?;
+        chunks.push((chunk_type, &bytes[offset + 8..end]));
+        offset = end;
+    }
+    match chunks.first() {
+        Some(&(GLB_CHUNK_JSON, json)) => {
+            let bin = chunks.get(1).filter(|(chunk_type, _)| *chunk_type == GLB_CHUNK_BIN).map(|&(_, bin)| bin);
+            Ok((json, bin))
+        }
+        _ => Err(malformed(file_path, "the first glb chunk must be json")),
+    }
+}
+
+fn check_asset(root: &JsonValue) -> Result<(), ObjParseErrorKind> {
+    let version = root.get("asset").and_then(|asset| asset.get("version")).and_then(JsonValue::as_str)
+        .ok_or(ObjParseErrorKind::Malformed("the asset version is missing"))?;
+    if !version.starts_with("2.") {
+        return Err(ObjParseErrorKind::Unsupported(format!("gltf version {}", version)));
+    }
+    // a required extension changes how the file must be read, so ignoring it is never an option
+    for extension in items(root, "extensionsRequired") {
+        let name = extension.as_str().unwrap_or_default();
+        if !SUPPORTED_EXTENSIONS.contains(&name) {
+            return Err(ObjParseErrorKind::Unsupported(format!("required extension {}", name)));
+        }
+    }
+    Ok(())
+}
+
+// buffers are base64 data uris, files next to the .gltf or, for the first one of a glb, its binary chunk.
+// the files read are dependencies of the cached mesh
+fn load_buffers(root: &JsonValue, bin_chunk: Option<&[u8]>, directory: &Path, file_path: &str, options: &ParseOptions, warnings: &mut Vec<ObjParseError>, dependencies: &mut Vec<String>) -> Result<Vec<Option<Vec<u8>>>, ObjParseError> {
+    let mut buffers = Vec::new();
+    for (i, buffer) in items(root, "buffers").iter().enumerate() {
+        let data = match buffer.get("uri").and_then(JsonValue::as_str) {
+            Some(uri) if uri.starts_with("data:") => decode_data_uri(uri).map_err(|kind| error(file_path, kind)),
+            Some(uri) => {
+                let path = directory.join(percent_decode(uri)).to_string_lossy().into_owned();
+                let data = fs::read(&path).map_err(|e| ObjParseError::io(&path, e));
+                dependencies.push(path);
+                data
+            }
+            None if i == 0 && bin_chunk.is_some() => Ok(bin_chunk.unwrap().to_vec()),
+            None => Err(malformed(file_path, "a buffer has no uri and there is no glb binary chunk")),
+        };
+        let byte_length = buffer.get("byteLength").and_then(JsonValue::as_usize).unwrap_or(0);
+        let data = data.and_then(|data| match data.len() < byte_length {
+            true => Err(malformed(file_path, "a buffer is shorter than its byteLength")),
+            false => Ok(data),
+        });
+        // the primitives reading a missing buffer are reported and skipped on their own
+        match data {
+            Ok(data) => buffers.push(Some(data)),
+            Err(error) => {
+                report(error, options, warnings)?;
+                buffers.push(None);
+            }
+        }
+    }
+    Ok(buffers)
+}
+
+// metallic surfaces have no diffuse colour and reflect their base colour, dielectric ones reflect 4%.
+// the roughness is turned into a phong exponent the usual way (2 / roughness^4 - 2)
+fn load_materials(document: &Document, file_path: &str, options: &ParseOptions, warnings: &mut Vec<ObjParseError>) -> Result<Vec<Material>, ObjParseError> {
+    let mut materials = Vec::new();
+    for (i, json) in items(document.root, "materials").iter().enumerate() {
+        let name = json.get("name").and_then(JsonValue::as_str).map_or_else(|| format!("material{}", i), str::to_string);
+        let mut material = Material::new(&name);
+        let pbr = json.get("pbrMetallicRoughness");
+        let factor = |key: &str| pbr.and_then(|pbr| pbr.get(key)).and_then(JsonValue::as_f64).unwrap_or(1.0).clamp(0.0, 1.0) as f32;
+        let (metallic, roughness) = (factor("metallicFactor"), factor("roughnessFactor"));
+        let base = pbr.and_then(|pbr| pbr.get("baseColorFactor")).and_then(|base| numbers(base, 4)).unwrap_or(vec![1.0; 4]);
+        let base_color = Vec3::new(base[0] as f32, base[1] as f32, base[2] as f32);
+
+        material.diffuse = base_color.scale(1.0 - metallic);
+        material.specular = Vec3::new(0.04, 0.04, 0.04).scale(1.0 - metallic).add(base_color.scale(metallic));
+        material.shininess = (2.0 / roughness.powi(4).max(1e-6) - 2.0).clamp(0.0, 1000.0);
+        if let Some(emissive) = json.get("emissiveFactor").and_then(|emissive| numbers(emissive, 3)) {
+            material.emissive = Vec3::new(emissive[0] as f32, emissive[1] as f32, emissive[2] as f32);
+        }
+        // masked and opaque materials ignore the alpha of their base colour
+        if json.get("alphaMode").and_then(JsonValue::as_str) == Some("BLEND") {
+            material.dissolve = base[3] as f32;
+        }
+        if let Some(texture) = pbr.and_then(|pbr| pbr.get("baseColorTexture")) {
+            if let Err(kind) = document.attach_texture(&mut material, texture) {
+                report(error(file_path, kind), options, warnings)?;
+            }
+        }
+        materials.push(material);
+    }
+    Ok(materials)
+}
+
+// the nodes of the default scene that carry a mesh, with the name of the node and its world transform.
+// files without scenes show their root nodes, files without nodes every mesh as it is
+fn mesh_instances(root: &JsonValue, file_path: &str, options: &ParseOptions, warnings: &mut Vec<ObjParseError>) -> Result<Vec<(String, usize, Mat4)>, ObjParseError> {
+    let nodes = items(root, "nodes");
+    let scenes = items(root, "scenes");
+    let roots: Vec<&JsonValue> = match root.get("scene").and_then(JsonValue::as_usize).or((!scenes.is_empty()).then_some(0)) {
+        Some(scene) => {
+            let scene = scenes.get(scene).ok_or_else(|| malformed(file_path, "the default scene doesn't exist"))?;
+            items(scene, "nodes").iter().collect()
+        }
+        None if !nodes.is_empty() => {
+            let children: Vec<usize> = nodes.iter().flat_map(|node| items(node, "children")).filter_map(JsonValue::as_usize).collect();
+            nodes.iter().enumerate().filter(|(i, _)| !children.contains(i)).map(|(_, node)| node).collect()
+        }
+        None => {
+            let instances = (0..items(root, "meshes").len()).map(|mesh| (String::new(), mesh, Mat4::identity()));
+            return Ok(instances.collect());
+        }
+    };
+
+    let mut instances = Vec::new();
+    // depth first, children in order. a node deeper than the number of nodes has to be part of a cycle
+    let mut stack: Vec<(Option<usize>, Mat4, usize)> = roots.iter().rev().map(|node| (node.as_usize(), Mat4::identity(), 0)).collect();
+    while let Some((index, parent, depth)) = stack.pop() {
+        if depth > nodes.len() {
+            return Err(malformed(file_path, "the node hierarchy has a cycle"));
+        }
+        let Some(node) = index.and_then(|index| nodes.get(index)) else {
+            report(malformed(file_path, "a reference to a node that doesn't exist"), options, warnings)?;
+            continue;
+        };
+        let transform = match local_transform(node) {
+            Ok(local) => local.multiply(&parent),
+            Err(kind) => {
+                // the whole subtree would be misplaced
+                report(error(file_path, kind), options, warnings)?;
+                continue;
+            }
+        };
+        if let Some(mesh) = node.get("mesh") {
+            let name = node.get("name").and_then(JsonValue::as_str).map_or_else(|| format!("node{}", index.unwrap()), str::to_string);
+            match mesh.as_usize() {
+                Some(mesh) => instances.push((name, mesh, transform)),
+                None => report(malformed(file_path, "a node refers to a mesh that doesn't exist"), options, warnings)?,
+            }
+        }
+        for child in items(node, "children").iter().rev() {
+            stack.push((child.as_usize(), transform, depth + 1));
+        }
+    }
+    Ok(instances)
+}
+
+// either a column-major matrix or translation, rotation (a quaternion) and scale applied as T * R * S
+fn local_transform(node: &JsonValue) -> Result<Mat4, ObjParseErrorKind> {
+    let mut transform = Mat4::identity();
+    if let Some(matrix) = node.get("matrix") {
+        let values = numbers(matrix, 16).ok_or(ObjParseErrorKind::Malformed("a node matrix must have 16 numbers"))?;
+        for (i, value) in values.iter().enumerate() {
+            transform.0[i / 4][i % 4] = *value as f32;
+        }
+        return Ok(transform);
+    }
+    let property = |key: &str, default: Vec<f64>| match node.get(key) {
+        Some(value) => numbers(value, default.len()).ok_or(ObjParseErrorKind::Malformed("invalid node translation, rotation or scale")),
+        None => Ok(default),
+    };
+    let translation = property("translation", vec![0.0; 3])?;
+    let [x, y, z, w] = property("rotation", vec![0.0, 0.0, 0.0, 1.0])?[..] else {
+        return Err(ObjParseErrorKind::Malformed("rotation needs 4 components"));
+    };
+    let scale = property("scale", vec![1.0; 3])?;
+
+    let rotation = [
+        [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + z * w), 2.0 * (x * z - y * w)],
+        [2.0 * (x * y - z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z + x * w)],
+        [2.0 * (x * z + y * w), 2.0 * (y * z - x * w), 1.0 - 2.0 * (x * x + y * y)],
+    ];
+    for (column, axis) in rotation.iter().enumerate() {
+        for (row, value) in axis.iter().enumerate() {
+            transform.0[column][row] = (value * scale[column]) as f32;
+        }
+        transform.0[3][column] = translation[column] as f32;
+    }
+    Ok(transform)
+}
+
+impl Document<'_> {
+    fn accessor(&self, index: Option<usize>, expected_components: &[usize], name: &'static str) -> Result<Accessor, ObjParseErrorKind> {
+        let accessor = index.and_then(|index| items(self.root, "accessors").get(index))
+            .ok_or(ObjParseErrorKind::Malformed("a reference to an accessor that doesn't exist"))?;
+        let component_type = required(accessor, "componentType")?;
+        let count = required(accessor, "count")?;
+        let components = match accessor.get("type").and_then(JsonValue::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            Some(matrix @ ("MAT2" | "MAT3" | "MAT4")) => return Err(ObjParseErrorKind::Unsupported(format!("{} accessors", matrix))),
+            _ => return Err(ObjParseErrorKind::Malformed("an accessor has no valid type")),
+        };
+        if !expected_components.contains(&components) {
+            return Err(ObjParseErrorKind::UnexpectedValue(name));
+        }
+        let normalized = accessor.get("normalized").and_then(JsonValue::as_bool).unwrap_or(false);
+        let byte_offset = optional(accessor, "byteOffset")?;
+
+        // without a buffer view every element starts at zero, which only makes sense with sparse values.
+        // nothing in the file backs those zeros, so the count is checked against the size of the buffers
+        // before they are allocated: a real model has at least a byte of data per element somewhere
+        let mut values = match accessor.get("bufferView") {
+            Some(view) => self.read_view(view.as_usize(), byte_offset, component_type, components, count, normalized)?,
+            None if accessor.get("sparse").is_none() => {
+                return Err(ObjParseErrorKind::Malformed("an accessor needs a buffer view or sparse values"));
+            }
+            None => {
+                let buffer_bytes: usize = self.buffers.iter().flatten().map(Vec::len).sum();
+                match count.checked_mul(components) {
+                    Some(len) if count <= buffer_bytes => vec![0.0; len],
+                    _ => return Err(ObjParseErrorKind::Malformed("a sparse accessor has more elements than the file holds")),
+                }
+            }
+        };
+        if let Some(sparse) = accessor.get("sparse") {
+            let sparse_count = required(sparse, "count")?;
+            let (Some(indices), Some(replacements)) = (sparse.get("indices"), sparse.get("values")) else {
+                return Err(ObjParseErrorKind::Malformed("a sparse accessor needs indices and values"));
+            };
+            let targets = self.read_view(required(indices, "bufferView").ok(), optional(indices, "byteOffset")?, required(indices, "componentType")?, 1, sparse_count, false)?;
+            let replacements = self.read_view(required(replacements, "bufferView").ok(), optional(replacements, "byteOffset")?, component_type, components, sparse_count, normalized)?;
+            for (target, replacement) in targets.iter().zip(replacements.chunks_exact(components)) {
+                let target = *target as usize;
+                if target >= count {
+                    return Err(ObjParseErrorKind::Malformed("a sparse accessor replaces an element past its end"));
+                }
+                values[target * components..(target + 1) * components].copy_from_slice(replacement);
+            }
+        }
+        Ok(Accessor {values, components})
+    }
+
+    fn read_view(&self, view: Option<usize>, byte_offset: usize, component_type: usize, components: usize, count: usize, normalized: bool) -> Result<Vec<f64>, ObjParseErrorKind> {
+        let bytes = self.view_bytes(view)?;
+        let size = match component_type {
+            COMPONENT_INT8 | COMPONENT_UINT8 => 1,
+            COMPONENT_INT16 | COMPONENT_UINT16 => 2,
+            COMPONENT_UINT32 | COMPONENT_FLOAT => 4,
+            _ => return Err(ObjParseErrorKind::Malformed("unknown accessor component type")),
+        };
+        let view = &items(self.root, "bufferViews")[view.unwrap()];
+        let stride = optional(view, "byteStride")?.max(size * components);
+        // the last element has to end inside the view, checked before anything is allocated.
+        // offsets come from the file, so every step is checked for overflow
+        let end = count.checked_sub(1)
+            .and_then(|last| last.checked_mul(stride))
+            .and_then(|last| last.checked_add(byte_offset))
+            .and_then(|last| last.checked_add(size * components));
+        if byte_offset > bytes.len() || end.is_some_and(|end| end > bytes.len()) || (end.is_none() && count > 0) {
+            return Err(ObjParseErrorKind::Malformed("an accessor goes past the end of its buffer view"));
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            let start = byte_offset + element * stride;
+            let element = &bytes[start..start + size * components];
+            values.extend(element.chunks_exact(size).map(|component| read_component(component, component_type, normalized)));
+        }
+        Ok(values)
+    }
+
+    fn view_bytes(&self, view: Option<usize>) -> Result<&[u8], ObjParseErrorKind> {
+        let view = view.and_then(|view| items(self.root, "bufferViews").get(view))
+            .ok_or(ObjParseErrorKind::Malformed("a reference to a buffer view that doesn't exist"))?;
+        let buffer = required(view, "buffer")?;
+        let buffer = self.buffers.get(buffer)
+            .ok_or(ObjParseErrorKind::Malformed("a reference to a buffer that doesn't exist"))?
+            .as_deref()
+            .ok_or(ObjParseErrorKind::Malformed("the buffer used by a buffer view could not be loaded"))?;
+        let start = optional(view, "byteOffset")?;
+        start.checked_add(required(view, "byteLength")?)
+            .and_then(|end| buffer.get(start..end))
+            .ok_or(ObjParseErrorKind::Malformed("a buffer view goes past the end of its buffer"))
+    }
+
+    // images are files next to the model, data uris or, in glb files, buffer views
+    fn attach_texture(&self, material: &mut Material, texture_info: &JsonValue) -> Result<(), ObjParseErrorKind> {
+        let image = texture_info.get("index").and_then(JsonValue::as_usize)
+            .and_then(|texture| items(self.root, "textures").get(texture))
+            .and_then(|texture| texture.get("source"))
+            .and_then(JsonValue::as_usize)
+            .and_then(|image| items(self.root, "images").get(image))
+            .ok_or(ObjParseErrorKind::Malformed("a material refers to a texture or an image that doesn't exist"))?;
+        match image.get("uri").and_then(JsonValue::as_str) {
+            Some(uri) if uri.starts_with("data:") => material.diffuse_image = Some(decode_data_uri(uri)?),
+            Some(uri) => material.diffuse_map = Some(self.directory.join(percent_decode(uri)).to_string_lossy().into_owned()),
+            None => material.diffuse_image = Some(self.view_bytes(image.get("bufferView").and_then(JsonValue::as_usize))?.to_vec()),
+        }
+        Ok(())
+    }
+}
+
+impl MeshBuilder {
+    // everything is read and checked before the first vertex is added, so that a broken primitive
+    // skipped in lenient mode leaves nothing behind
+    fn add_primitive(&mut self, document: &Document, primitive: &JsonValue, transform: &Mat4, num_materials: usize) -> Result<(), ObjParseErrorKind> {
+        let attributes = primitive.get("attributes").ok_or(ObjParseErrorKind::Malformed("a primitive has no attributes"))?;
+        let attribute = |name: &str| attributes.get(name).map(JsonValue::as_usize);
+        let positions = document.accessor(attribute("POSITION").ok_or(ObjParseErrorKind::MissingValue("POSITION attribute"))?, &[3], "POSITION must be a VEC3")?;
+        let count = positions.count();
+        let optional_attribute = |name: &str, components: &[usize], expected: &'static str| -> Result<Option<Accessor>, ObjParseErrorKind> {
+            let Some(index) = attribute(name) else {
+                return Ok(None);
+            };
+            let accessor = document.accessor(index, components, expected)?;
+            if accessor.count() != count {
+                return Err(ObjParseErrorKind::Malformed("the attributes of a primitive don't have the same count"));
+            }
+            Ok(Some(accessor))
+        };
+        let normals = optional_attribute("NORMAL", &[3], "NORMAL must be a VEC3")?;
+        let texture_coords = optional_attribute("TEXCOORD_0", &[2], "TEXCOORD_0 must be a VEC2")?;
+        let colors = optional_attribute("COLOR_0", &[3, 4], "COLOR_0 must be a VEC3 or a VEC4")?;
+
+        let indices: Vec<usize> = match primitive.get("indices") {
+            Some(indices) => {
+                let indices = document.accessor(indices.as_usize(), &[1], "indices must be scalars")?;
+                indices.values.iter()
+                    .map(|&index| match index >= 0.0 && index.fract() == 0.0 && (index as usize) < count {
+                        true => Ok(index as usize),
+                        false => Err(ObjParseErrorKind::IndexOutOfRange("vertex")),
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+            None => (0..count).collect(),
+        };
+        let material = match primitive.get("material") {
+            Some(material) => Some(material.as_usize().filter(|&material| material < num_materials)
+                .ok_or(ObjParseErrorKind::Malformed("a primitive refers to a material that doesn't exist"))?),
+            None => None,
+        };
+        let mode = primitive.get("mode").map_or(Some(4), JsonValue::as_usize).filter(|&mode| mode <= 6)
+            .ok_or(ObjParseErrorKind::Malformed("unknown primitive mode"))?;
+
+        let base = self.vertices.len();
+        for i in 0..count {
+            let position = transform_point(transform, positions.vec3(i));
+            let normal = normals.as_ref().map_or(Vec3::new(0.0, 0.0, 0.0), |normals| transform_normal(transform, normals.vec3(i)));
+            // gltf texture coordinates start at the top of the image
+            let (text_x, text_y) = texture_coords.as_ref()
+                .map_or((position.z, position.y), |coords| (coords.get(i, 0) as f32, 1.0 - coords.get(i, 1) as f32));
+            // primitives without colours are white when others have some, as the specification says
+            let rgb = match &colors {
+                Some(colors) => colors.vec3(i),
+                None if self.has_vertex_colors => Vec3::new(1.0, 1.0, 1.0),
+                None => generate_random_color(self.vertices.len() as u32),
+            };
+            self.vertices.push(Vertex {position, rgb, text_x, text_y, normal, properties: Vec::new()});
+        }
+
+        let vertex = |i: usize| (base + indices[i]) as GLuint;
+        let n = indices.len();
+        let triangles: Vec<[GLuint; 3]> = match mode {
+            0 => {
+                self.points.extend((0..n).map(vertex));
+                Vec::new()
+            }
+            1..=3 => {
+                let mut segments: Vec<(usize, usize)> = match mode {
+                    1 => (0..n / 2).map(|i| (i * 2, i * 2 + 1)).collect(),
+                    _ => (1..n).map(|i| (i - 1, i)).collect(),
+                };
+                if mode == 2 && n > 2 {
+                    segments.push((n - 1, 0));
+                }
+                self.lines.extend(segments.into_iter().flat_map(|(a, b)| [vertex(a), vertex(b)]));
+                Vec::new()
+            }
+            4 => (0..n / 3).map(|i| [vertex(i * 3), vertex(i * 3 + 1), vertex(i * 3 + 2)]).collect(),
+            // every other triangle of a strip is wound the other way
+            5 => (2..n).map(|i| if i % 2 == 0 { [vertex(i - 2), vertex(i - 1), vertex(i)] } else { [vertex(i - 1), vertex(i - 2), vertex(i)] }).collect(),
+            _ => (2..n).map(|i| [vertex(0), vertex(i - 1), vertex(i)]).collect(),
+        };
+
+        // a mirroring transform turns the faces inside out
+        let mirrored = determinant(transform) < 0.0;
+        // the specification asks for flat normals when none are given
+        let smoothing_group = if normals.is_some() { 1 } else { 0 };
+        for [a, b, c] in triangles {
+            self.triangles.extend(if mirrored { [a, c, b] } else { [a, b, c] });
+            self.triangle_materials.push(material);
+            self.smoothing_groups.push(smoothing_group);
+        }
+        Ok(())
+    }
+}
+
+// columns of the linear part of the transform
+fn axes(transform: &Mat4) -> [Vec3; 3] {
+    [0, 1, 2].map(|column| Vec3::new(transform.0[column][0], transform.0[column][1], transform.0[column][2]))
+}
+
+fn determinant(transform: &Mat4) -> f32 {
+    let [x, y, z] = axes(transform);
+    x.dot(y.cross(z))
+}
+
+fn transform_point(transform: &Mat4, point: Vec3) -> Vec3 {
+    let [x, y, z] = axes(transform);
+    let translation = Vec3::new(transform.0[3][0], transform.0[3][1], transform.0[3][2]);
+    x.scale(point.x).add(y.scale(point.y)).add(z.scale(point.z)).add(translation)
+}
+
+// normals go through the inverse transpose, here its cofactor matrix which only differs by the determinant.
+// a zero normal stays zero so that it is generated later
+fn transform_normal(transform: &Mat4, normal: Vec3) -> Vec3 {
+    let [x, y, z] = axes(transform);
+    let sign = determinant(transform).signum();
+    let normal = y.cross(z).scale(normal.x).add(z.cross(x).scale(normal.y)).add(x.cross(y).scale(normal.z)).scale(sign);
+    if normal.length().is_normal() { normal.normalize() } else { Vec3::new(0.0, 0.0, 0.0) }
+}
+
+fn read_component(bytes: &[u8], component_type: usize, normalized: bool) -> f64 {
+    let value = match component_type {
+        COMPONENT_INT8 => bytes[0] as i8 as f64,
+        COMPONENT_UINT8 => bytes[0] as f64,
+        COMPONENT_INT16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        COMPONENT_UINT16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        COMPONENT_UINT32 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        _ => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+    };
+    if !normalized {
+        return value;
+    }
+    // signed values are scaled so that the largest one is 1, the smallest one is clamped to -1
+    match component_type {
+        COMPONENT_INT8 => (value / i8::MAX as f64).max(-1.0),
+        COMPONENT_UINT8 => value / u8::MAX as f64,
+        COMPONENT_INT16 => (value / i16::MAX as f64).max(-1.0),
+        COMPONENT_UINT16 => value / u16::MAX as f64,
+        _ => value,
+    }
+}
+
+// every triangle is exactly 3 indices
+fn get_material_ranges(triangle_materials: &[Option<usize>]) -> Vec<MaterialRange> {
+    let mut ranges: Vec<MaterialRange> = Vec::new();
+    for (i, &material) in triangle_materials.iter().enumerate() {
+        match ranges.last_mut() {
+            Some(range) if range.material == material => range.num_indices += 3,
+            _ => ranges.push(MaterialRange {material, first_index: i * 3, num_indices: 3}),
+        }
+    }
+    ranges
+}
+
+// data:[<media type>][;base64],<data>
+fn decode_data_uri(uri: &str) -> Result<Vec<u8>, ObjParseErrorKind> {
+    let (header, data) = uri["data:".len()..].split_once(',').ok_or(ObjParseErrorKind::Malformed("a data uri has no ','"))?;
+    if !header.ends_with(";base64") {
+        return Err(ObjParseErrorKind::Unsupported("data uris that aren't base64".to_string()));
+    }
+    decode_base64(data).ok_or(ObjParseErrorKind::Malformed("invalid base64 in a data uri"))
+}
+
+// standard or url-safe alphabet, padding is optional
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut bits, mut num_bits) = (0u32, 0);
+    for c in text.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | value as u32;
+        num_bits += 6;
+        if num_bits >= 8 {
+            num_bits -= 8;
+            bytes.push((bits >> num_bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+// relative uris escape spaces and other characters as %XX
+fn percent_decode(uri: &str) -> String {
+    let mut bytes = Vec::with_capacity(uri.len());
+    let mut i = 0;
+    while i < uri.len() {
+        let escaped = uri.get(i + 1..i + 3).filter(|_| uri.as_bytes()[i] == b'%').and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(uri.as_bytes()[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn line_and_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+// the members of an array property, empty when it isn't there
+fn items<'a>(value: &'a JsonValue, key: &str) -> &'a [JsonValue] {
+    value.get(key).and_then(JsonValue::as_array).unwrap_or_default()
+}
+
+fn numbers(value: &JsonValue, len: usize) -> Option<Vec<f64>> {
+    let values = value.as_array().filter(|values| values.len() == len)?;
+    values.iter().map(JsonValue::as_f64).collect()
+}
+
+fn required(value: &JsonValue, key: &'static str) -> Result<usize, ObjParseErrorKind> {
+    value.get(key).and_then(JsonValue::as_usize).ok_or(ObjParseErrorKind::MissingValue(key))
+}
+
+// offsets and strides default to zero
+fn optional(value: &JsonValue, key: &'static str) -> Result<usize, ObjParseErrorKind> {
+    value.get(key).map_or(Ok(0), |_| required(value, key))
+}
+
+fn report(error: ObjParseError, options: &ParseOptions, warnings: &mut Vec<ObjParseError>) -> Result<(), ObjParseError> {
+    if !options.is_lenient() {
+        return Err(error);
+    }
+    warnings.push(error);
+    Ok(())
+}
+
+fn error(file_path: &str, kind: ObjParseErrorKind) -> ObjParseError {
+    ObjParseError::new(file_path, 0, 0, "", kind)
+}
+
+fn malformed(file_path: &str, reason: &'static str) -> ObjParseError {
+    error(file_path, ObjParseErrorKind::Malformed(reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{write_test_file, LENIENT, STRICT};
+
+    // a triangle: its positions then its 16 bit indices, padded to 4 bytes
+    fn triangle_buffer(indices: [u16; 3]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0].iter().for_each(|value| bytes.extend(value.to_le_bytes()));
+        indices.iter().for_each(|index| bytes.extend(index.to_le_bytes()));
+        bytes.extend([0, 0]);
+        bytes
+    }
+
+    // a node placing the triangle 5 units away, buffer is the start of the buffer object
+    fn triangle_document(buffer: &str) -> String {
+        format!(r#"{{
+            "asset": {{"version": "2.0"}},
+            "scene": 0,
+            "scenes": [{{"nodes": [0]}}],
+            "nodes": [{{"name": "tri", "mesh": 0, "translation": [0, 0, 5]}}],
+            "meshes": [{{"name": "shape", "primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1}}]}}],
+            "accessors": [
+                {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+                {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+            ],
+            "bufferViews": [{{"buffer": 0, "byteLength": 36}}, {{"buffer": 0, "byteOffset": 36, "byteLength": 6}}],
+            "buffers": [{{{}"byteLength": 44}}]
+        }}"#, buffer)
+    }
+
+    // chunks are padded to 4 bytes, with spaces for the json
+    fn glb(version: u32, chunks: &[(u32, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for &(chunk_type, data) in chunks {
+            let padding = if chunk_type == GLB_CHUNK_JSON { b' ' } else { 0 };
+            let mut data = data.to_vec();
+            data.resize(data.len().div_ceil(4) * 4, padding);
+            body.extend((data.len() as u32).to_le_bytes());
+            body.extend(chunk_type.to_le_bytes());
+            body.extend(data);
+        }
+        let mut bytes = GLB_MAGIC.to_vec();
+        bytes.extend(version.to_le_bytes());
+        bytes.extend(((GLB_HEADER_SIZE + body.len()) as u32).to_le_bytes());
+        bytes.extend(body);
+        bytes
+    }
+
+    #[test]
+    fn external_buffer() {
+        let buffer = write_test_file("gltf-external.bin", &triangle_buffer([0, 1, 2]));
+        let uri = Path::new(&buffer).file_name().unwrap().to_string_lossy().into_owned();
+        let contents = triangle_document(&format!(r#""uri": "{}", "#, uri));
+        let (obj_data, warnings) = parse_gltf_file(&write_test_file("gltf-external.gltf", contents.as_bytes()), &STRICT).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!((obj_data.vertices.len(), obj_data.num_indices), (3, 3));
+        assert_eq!(obj_data.vertices[1].position.z, 5.0);
+        assert_eq!(obj_data.dependencies, [buffer]);
+        assert!(obj_data.sub_meshes.iter().any(|sub_mesh| sub_mesh.object == "tri" && sub_mesh.group == "shape"));
+    }
+
+    #[test]
+    fn glb_binary_chunk() {
+        let json = triangle_document("");
+        let bytes = glb(2, &[(GLB_CHUNK_JSON, json.as_bytes()), (GLB_CHUNK_BIN, &triangle_buffer([0, 1, 2]))]);
+        assert!(looks_like_gltf(&bytes));
+        let (obj_data, warnings) = parse_gltf_file(&write_test_file("gltf-binary.glb", &bytes), &STRICT).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(obj_data.num_indices, 3);
+        assert_eq!(obj_data.vertices[2].position.y, 1.0);
+    }
+
+    #[test]
+    fn glb_chunks() {
+        let json = br#"{"asset": {"version": "2.0"}}"#;
+        let json_only = glb(2, &[(GLB_CHUNK_JSON, json)]);
+        let (found, bin) = split_glb(&json_only, "").unwrap();
+        assert_eq!((found.trim_ascii_end(), bin), (&json[..], None));
+        let with_bin = glb(2, &[(GLB_CHUNK_JSON, json), (GLB_CHUNK_BIN, &[1, 2, 3, 4])]);
+        assert_eq!(split_glb(&with_bin, "").unwrap().1, Some(&[1u8, 2, 3, 4][..]));
+        // a binary chunk only counts right after the json one
+        let late_bin = glb(2, &[(GLB_CHUNK_JSON, json), (0x12345678, &[0; 4]), (GLB_CHUNK_BIN, &[1, 2, 3, 4])]);
+        assert_eq!(split_glb(&late_bin, "").unwrap().1, None);
+
+        assert!(split_glb(&glb(1, &[(GLB_CHUNK_JSON, json)]), "").is_err());
+        assert!(split_glb(&glb(2, &[(GLB_CHUNK_BIN, &[0; 4]), (GLB_CHUNK_JSON, json)]), "").is_err());
+        assert!(split_glb(&glb(2, &[]), "").is_err());
+        assert!(split_glb(GLB_MAGIC, "").is_err());
+        let mut truncated = glb(2, &[(GLB_CHUNK_JSON, json)]);
+        truncated.truncate(truncated.len() - 4);
+        assert!(split_glb(&truncated, "").is_err());
+        // a chunk longer than the length given in the header
+        let mut overlong = glb(2, &[(GLB_CHUNK_JSON, json)]);
+        overlong[GLB_HEADER_SIZE] += 8;
+        assert!(split_glb(&overlong, "").is_err());
+    }
+
+    // the triangle of triangle_document with one of its pieces replaced
+    fn edited_triangle(name: &str, from: &str, to: &str) -> String {
+        let json = triangle_document("").replace(from, to);
+        let bytes = glb(2, &[(GLB_CHUNK_JSON, json.as_bytes()), (GLB_CHUNK_BIN, &triangle_buffer([0, 1, 2]))]);
+        write_test_file(name, &bytes)
+    }
+
+    fn malformed_reason(file_path: &str) -> &'static str {
+        match parse_gltf_file(file_path, &STRICT).err().unwrap().kind {
+            ObjParseErrorKind::Malformed(reason) => reason,
+            kind => panic!("{:?}", kind),
+        }
+    }
+
+    #[test]
+    fn accessors_stay_inside_their_views() {
+        let positions = r#"{"bufferView": 0, "componentType""#;
+        for offset in ["4", "40", "1e18"] {
+            let file_path = edited_triangle("gltf-offset.glb", positions, &format!(r#"{{"bufferView": 0, "byteOffset": {}, "componentType""#, offset));
+            assert_eq!(malformed_reason(&file_path), "an accessor goes past the end of its buffer view", "byteOffset {}", offset);
+        }
+        let file_path = edited_triangle("gltf-count.glb", r#""count": 3, "type": "VEC3""#, r#""count": 1e18, "type": "VEC3""#);
+        assert_eq!(malformed_reason(&file_path), "an accessor goes past the end of its buffer view");
+        // an index past the vertices drops the primitive
+        let json = triangle_document("");
+        let bytes = glb(2, &[(GLB_CHUNK_JSON, json.as_bytes()), (GLB_CHUNK_BIN, &triangle_buffer([0, 1, 7]))]);
+        let (obj_data, warnings) = parse_gltf_file(&write_test_file("gltf-range.glb", &bytes), &LENIENT).unwrap();
+        assert_eq!((warnings.len(), obj_data.num_indices), (1, 0));
+    }
+
+    #[test]
+    fn sparse_accessors_without_a_view() {
+        let positions = r#"{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}"#;
+        let sparse = |count: &str| format!(r#"{{"componentType": 5126, "count": {}, "type": "VEC3",
+            "sparse": {{"count": 3, "indices": {{"bufferView": 1, "componentType": 5123}}, "values": {{"bufferView": 0}}}}}}"#, count);
+        // every element replaced by a sparse value gives the same triangle
+        let file_path = edited_triangle("gltf-sparse.glb", positions, &sparse("3"));
+        let (obj_data, _) = parse_gltf_file(&file_path, &STRICT).unwrap();
+        assert_eq!(obj_data.num_indices, 3);
+        assert_eq!(obj_data.vertices[1].position.x, 1.0);
+
+        // zeros are not allocated for a count no file could back
+        let file_path = edited_triangle("gltf-sparse-count.glb", positions, &sparse("1e15"));
+        assert_eq!(malformed_reason(&file_path), "a sparse accessor has more elements than the file holds");
+        let file_path = edited_triangle("gltf-no-view.glb", positions, r#"{"componentType": 5126, "count": 3, "type": "VEC3"}"#);
+        assert_eq!(malformed_reason(&file_path), "an accessor needs a buffer view or sparse values");
+    }
+
+    #[test]
+    fn node_rotations() {
+        let translation = r#""translation": [0, 0, 5]"#;
+        let file_path = edited_triangle("gltf-rotation.glb", translation, r#""rotation": [0, 0, 0.70710678, 0.70710678]"#);
+        let (obj_data, _) = parse_gltf_file(&file_path, &STRICT).unwrap();
+        // a quarter turn around z takes x to y
+        let position = obj_data.vertices[1].position;
+        assert!(position.x.abs() < 1e-6 && (position.y - 1.0).abs() < 1e-6, "{:?}", position);
+        let file_path = edited_triangle("gltf-bad-rotation.glb", translation, r#""rotation": [0, 0, 1]"#);
+        assert!(matches!(parse_gltf_file(&file_path, &STRICT).err().unwrap().kind, ObjParseErrorKind::Malformed(_)));
+    }
+
+    #[test]
+    fn missing_buffer_files() {
+        let contents = triangle_document(r#""uri": "gltf-missing.bin", "#);
+        let file_path = write_test_file("gltf-missing.gltf", contents.as_bytes());
+        assert!(matches!(parse_gltf_file(&file_path, &STRICT).err().unwrap().kind, ObjParseErrorKind::Io(_)));
+        // the buffer is reported once, then the primitive reading it
+        let (obj_data, warnings) = parse_gltf_file(&file_path, &LENIENT).unwrap();
+        assert_eq!((warnings.len(), obj_data.num_indices), (2, 0));
+    }
+
+    #[test]
+    fn document_errors() {
+        let error = parse_gltf_file(&write_test_file("gltf-syntax.gltf", b"{\n  \"asset\": {\"version\": \"2.0\",}\n}"), &LENIENT).err().unwrap();
+        assert_eq!((error.line_number, error.column), (2, 30));
+        let cases = [
+            r#"{"asset": {"version": "1.0"}}"#,
+            r#"{"asset": {}}"#,
+            r#"{"asset": {"version": "2.0"}, "extensionsRequired": ["KHR_draco_mesh_compression"]}"#,
+            r#"{"asset": {"version": "2.0"}, "scene": 1, "scenes": [{"nodes": []}]}"#,
+        ];
+        for contents in cases {
+            assert!(parse_gltf_file(&write_test_file("gltf-document.gltf", contents.as_bytes()), &LENIENT).is_err(), "{}", contents);
+        }
+        let quantized = r#"{"asset": {"version": "2.0"}, "extensionsRequired": ["KHR_mesh_quantization"]}"#;
+        assert!(parse_gltf_file(&write_test_file("gltf-quantized.gltf", quantized.as_bytes()), &STRICT).is_ok());
+    }
+
+    #[test]
+    fn uris() {
+        assert_eq!(decode_base64("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(decode_base64("aGVsbG8").unwrap(), b"hello");
+        assert_eq!(decode_base64("-_8").unwrap(), [0xfb, 0xff]);
+        assert!(decode_base64("a b").is_none());
+        assert_eq!(decode_data_uri("data:application/octet-stream;base64,AQID").unwrap(), [1, 2, 3]);
+        assert!(decode_data_uri("data:text/plain,abc").is_err());
+        assert_eq!(percent_decode("my%20data.bin"), "my data.bin");
+        assert_eq!(percent_decode("100%.bin"), "100%.bin");
+    }
+}
@@ -1,7 +1,7 @@
 use gl::types::{GLfloat, GLsizei, GLsizeiptr, GLuint, GLvoid};
 use glfw::Context;
 
-use std::{io::{Error, ErrorKind}, mem::size_of, os::raw::c_void};
+use std::{io::Error, mem::size_of, os::raw::c_void};
 
 use crate::models::{gl_var::GlVar, obj_data::ObjData};
 
@@ -10,7 +10,7 @@ pub fn init_window(width: u32, height: u32) -> Result<GlVar, Error> {
     println!("Initializing glfw...");
     let mut glfw = match glfw::init(glfw::FAIL_ON_ERRORS) {
         Ok(glfw) => glfw,
-        Err(e) => return Err(Error::new(ErrorKind::Other, e)),
+        Err(e) => return Err(Error::other(e)),
     };
     glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
     glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
@@ -28,13 +28,16 @@ pub fn init_window(width: u32, height: u32) -> Result<GlVar, Error> {
 
     gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
     
-    Ok(GlVar {glfw: glfw, window: window, events: events, shader_prgm_id: 0, texture_id: 0})
+    Ok(GlVar {glfw, window, events, shader_prgm_id: 0, texture_id: 0})
 }
 
 pub unsafe fn send_data_to_opengl(obj_data: &ObjData) -> (GLuint, GLuint, GLuint) {
 
     let (mut vbo, mut vao, mut ebo) = (0, 0, 0);
     gl::Enable(gl::DEPTH_TEST);
+    // materials with a dissolve below 1 are see-through
+    gl::Enable(gl::BLEND);
+    gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
     gl::GenVertexArrays(1, &mut vao);
     gl::GenBuffers(1, &mut vbo);
     gl::GenBuffers(1, &mut ebo);
@@ -50,9 +53,9 @@ pub unsafe fn send_data_to_opengl(obj_data: &ObjData) -> (GLuint, GLuint, GLuint
     gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
     gl::BufferData(gl::ELEMENT_ARRAY_BUFFER,
                     obj_data.indices_buffer_size as GLsizeiptr,
-                    obj_data.indices.as_ptr() as *const c_void,
+                    obj_data.indices.as_ptr(),
                     gl::STATIC_DRAW);
-    let stride: i32 = 8 * size_of::<GLfloat>() as GLsizei;
+    let stride: i32 = 11 * size_of::<GLfloat>() as GLsizei;
     //position attribute
     gl::VertexAttribPointer(
         0,
@@ -80,5 +83,14 @@ pub unsafe fn send_data_to_opengl(obj_data: &ObjData) -> (GLuint, GLuint, GLuint
         stride,
         (6 * size_of::<GLfloat>()) as *const c_void);
     gl::EnableVertexAttribArray(2);
+    //normal attribute
+    gl::VertexAttribPointer(
+        3,
+        3,
+        gl::FLOAT,
+        gl::FALSE,
+        stride,
+        (8 * size_of::<GLfloat>()) as *const c_void);
+    gl::EnableVertexAttribArray(3);
     (vao, vbo, ebo)
 }
\ No newline at end of file
@@ -0,0 +1,233 @@
+// small json reader, enough for gltf documents: the text is parsed into a tree of values.
+// objects keep their members in file order
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+// deeper documents are refused rather than overflowing the stack
+const MAX_DEPTH: usize = 256;
+
+impl JsonValue {
+    // member of an object, none for anything else
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // only for numbers that are whole and not negative
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_f64().filter(|value| *value >= 0.0 && value.fract() == 0.0).map(|value| value as usize)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+// the error is the byte offset where the text stops making sense, with what was expected there
+pub fn parse_json(text: &str) -> Result<JsonValue, (usize, &'static str)> {
+    let mut parser = Parser {bytes: text.as_bytes(), position: 0};
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.position < parser.bytes.len() {
+        return Err((parser.position, "unexpected data after the end of the document"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.bytes.get(self.position).is_some_and(|b| b.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.position).copied()
+    }
+
+    fn expect(&mut self, byte: u8, error: &'static str) -> Result<(), (usize, &'static str)> {
+        if self.peek() != Some(byte) {
+            return Err((self.position, error));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str, value: JsonValue) -> Result<JsonValue, (usize, &'static str)> {
+        if !self.bytes[self.position..].starts_with(word.as_bytes()) {
+            return Err((self.position, "expected a value"));
+        }
+        self.position += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<JsonValue, (usize, &'static str)> {
+        if depth > MAX_DEPTH {
+            return Err((self.position, "the document is nested too deeply"));
+        }
+        match self.peek() {
+            Some(b'{') => self.object(depth),
+            Some(b'[') => self.array(depth),
+            Some(b'"') => Ok(JsonValue::String(self.string()?)),
+            Some(b't') => self.literal("true", JsonValue::Bool(true)),
+            Some(b'f') => self.literal("false", JsonValue::Bool(false)),
+            Some(b'n') => self.literal("null", JsonValue::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => Err((self.position, "expected a value")),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<JsonValue, (usize, &'static str)> {
+        self.position += 1;
+        let mut members = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err((self.position, "expected a member name"));
+            }
+            let name = self.string()?;
+            self.expect(b':', "expected ':' after a member name")?;
+            members.push((name, self.value(depth + 1)?));
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(JsonValue::Object(members));
+                }
+                _ => return Err((self.position, "expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<JsonValue, (usize, &'static str)> {
+        self.position += 1;
+        let mut values = Vec::new();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(JsonValue::Array(values));
+        }
+        loop {
+            values.push(self.value(depth + 1)?);
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(JsonValue::Array(values));
+                }
+                _ => return Err((self.position, "expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<JsonValue, (usize, &'static str)> {
+        let start = self.position;
+        while self.bytes.get(self.position).is_some_and(|b| matches!(b, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+            self.position += 1;
+        }
+        // the bytes are all ascii
+        let text = std::str::from_utf8(&self.bytes[start..self.position]).unwrap();
+        text.parse::<f64>().map(JsonValue::Number).map_err(|_| (start, "invalid number"))
+    }
+
+    fn string(&mut self) -> Result<String, (usize, &'static str)> {
+        let start = self.position;
+        self.position += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let Some(&byte) = self.bytes.get(self.position) else {
+                return Err((start, "unterminated string"));
+            };
+            self.position += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.bytes.get(self.position) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err((self.position, "invalid escape sequence")),
+                    };
+                    self.position += 1;
+                    let mut buffer = [0; 4];
+                    bytes.extend(escaped.encode_utf8(&mut buffer).as_bytes());
+                }
+                _ => bytes.push(byte),
+            }
+        }
+        // the text came from a str and escapes are valid chars, so this can't fail
+        Ok(String::from_utf8(bytes).unwrap())
+    }
+
+    // \uXXXX, with characters outside the basic plane written as a pair of surrogates.
+    // leaves the position on the last digit
+    fn unicode_escape(&mut self) -> Result<char, (usize, &'static str)> {
+        let first = self.hex_digits(self.position + 1)?;
+        self.position += 4;
+        if !(0xD800..0xDC00).contains(&first) {
+            return char::from_u32(first).ok_or((self.position, "invalid unicode escape"));
+        }
+        if !self.bytes[self.position + 1..].starts_with(b"\\u") {
+            return Err((self.position, "unpaired surrogate in unicode escape"));
+        }
+        let second = self.hex_digits(self.position + 3)?;
+        self.position += 6;
+        if !(0xDC00..0xE000).contains(&second) {
+            return Err((self.position, "unpaired surrogate in unicode escape"));
+        }
+        char::from_u32(0x10000 + ((first - 0xD800) << 10) + (second - 0xDC00)).ok_or((self.position, "invalid unicode escape"))
+    }
+
+    fn hex_digits(&self, start: usize) -> Result<u32, (usize, &'static str)> {
+        let digits = self.bytes.get(start..start + 4).ok_or((start, "invalid unicode escape"))?;
+        let digits = std::str::from_utf8(digits).map_err(|_| (start, "invalid unicode escape"))?;
+        u32::from_str_radix(digits, 16).map_err(|_| (start, "invalid unicode escape"))
+    }
+}
@@ -1,30 +1,114 @@
 use std::env;
+use std::str::FromStr;
 
-use globals::{WIN_HEIGHT, WIN_WIDTH};
+use globals::{WIN_HEIGHT, WIN_WIDTH, CREASE_ANGLE, LINE_WIDTH, POINT_SIZE, TESSELLATION_RESOLUTION};
+use mesh_loader::{LoaderRegistry, MeshLoader};
+use mesh_validation::Severity;
+use models::obj_data::ObjData;
+use models::parse_options::{ParseOptions, Strictness};
 
 extern crate gl;
 extern crate glfw;
 
 mod obj_parser;
+mod gltf_parser;
+mod json;
+mod mtl_parser;
+mod obj_tokenizer;
+mod obj_writer;
+mod off_parser;
+mod ply_parser;
+mod stl_parser;
+mod mapped_file;
+mod mesh_cache;
+mod mesh_loader;
+mod mesh_processing;
+mod mesh_validation;
+mod triangulation;
+mod freeform;
 mod models;
 mod globals;
 mod init_opengl;
 mod compile_shaders;
 mod texture_loader;
 mod render;
+#[cfg(test)]
+mod test_support;
 
 fn main() -> Result<(), String> {
-    let args: Vec<String> = env::args().collect();
+    let usage = "Usage: ./scop [--strict] [--line-width width] [--point-size size] [--resolution segments] [--weld epsilon] [--export path/to/output.obj] [--no-cache] [--color-by property] [--format obj|stl|ply|off|gltf] path/to/object/file path/to/bmp/texture";
+    let mut strictness = Strictness::Lenient;
+    let mut line_width = LINE_WIDTH;
+    let mut point_size = POINT_SIZE;
+    let mut resolution = TESSELLATION_RESOLUTION;
+    let mut weld_epsilon = None;
+    let mut export_path = None;
+    let mut use_cache = true;
+    let mut color_property = None;
+    let mut format = None;
+    let mut args: Vec<String> = Vec::new();
+    let mut arg_iter = env::args();
+    while let Some(arg) = arg_iter.next() {
+        match arg.as_str() {
+            "--strict" => strictness = Strictness::Strict,
+            "--no-cache" => use_cache = false,
+            "--line-width" => line_width = parse_positive_argument(&arg, arg_iter.next(), usage)?,
+            "--point-size" => point_size = parse_positive_argument(&arg, arg_iter.next(), usage)?,
+            "--resolution" => resolution = parse_positive_argument(&arg, arg_iter.next(), usage)?,
+            "--weld" => weld_epsilon = Some(parse_positive_argument(&arg, arg_iter.next(), usage)?),
+            "--color-by" => color_property = Some(arg_iter.next().ok_or_else(|| format!("Error: --color-by expects a property name\n{}", usage))?),
+            "--format" => format = Some(arg_iter.next().ok_or_else(|| format!("Error: --format expects a format name\n{}", usage))?),
+            "--export" => export_path = Some(arg_iter.next().ok_or_else(|| format!("Error: --export expects a file path\n{}", usage))?),
+            _ => args.push(arg),
+        }
+    }
 
     if args.len() != 3 {
-        return Err("Error: Wrong number of arguments\nUsage: ./scop path/to/object/file path/to/bmp/texture".to_string());
+        return Err(format!("Error: Wrong number of arguments\n{}", usage));
     }
 
-    let mut objdata = match obj_parser::parse_obj_file(&args[1]) {
-        Ok(data) => data,
-        Err(err) => return Err(format!("Error while parsing obj file: {}", err)),
+    let registry = LoaderRegistry::new();
+    let loader = match &format {
+        Some(name) => registry.find(name).ok_or_else(|| format!("Error: unknown format {}, expected one of {}\n{}", name, registry.names().join(", "), usage))?,
+        None => registry.detect(&args[1]),
+    };
+
+    let options = ParseOptions {strictness, resolution};
+    let cached = if use_cache { mesh_cache::load_cached_mesh(&args[1], loader.name(), resolution) } else { None };
+    let mut objdata = match cached {
+        Some(data) => data,
+        None => parse_and_cache(loader, &args[1], &options, use_cache)?,
     };
 
+    let issues = mesh_validation::validate_mesh(&mut objdata, strictness);
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    if issues.iter().any(|issue| issue.severity == Severity::Error) {
+        return Err("Error while validating mesh".to_string());
+    }
+
+    if let Some(epsilon) = weld_epsilon {
+        let removed = mesh_processing::weld_vertices(&mut objdata, epsilon);
+        println!("Welding removed {} of {} vertices", removed, objdata.num_vertices as usize + removed);
+    }
+
+    if let Err(err) = mesh_processing::generate_missing_normals(&mut objdata, CREASE_ANGLE) {
+        return Err(format!("Error while generating normals: {}", err));
+    }
+    if let Some(property) = &color_property {
+        if let Err(err) = mesh_processing::color_by_property(&mut objdata, property) {
+            return Err(format!("Error while coloring the mesh: {}", err));
+        }
+    }
+    if let Some(export_path) = &export_path {
+        if let Err(err) = obj_writer::write_obj_file(&objdata, export_path) {
+            return Err(format!("Error while exporting obj file: {}", err));
+        }
+    }
+    objdata.line_width = line_width;
+    objdata.point_size = point_size;
+
     let mut glvar = match init_opengl::init_window(WIN_WIDTH, WIN_HEIGHT) {
         Ok(vars) => vars,
         Err(err) => return Err(format!("Error while initializing window: {}", err)),
@@ -42,6 +126,8 @@ fn main() -> Result<(), String> {
         Err(err) => return Err(format!("Error while loading texture: {}", err)),
     };
 
+    texture_loader::load_material_textures(&mut objdata.materials);
+
     render::render_loop(&mut glvar, &vao, &mut objdata);
 
     // Clean up OpenGL resources
@@ -49,7 +135,41 @@ fn main() -> Result<(), String> {
         gl::DeleteVertexArrays(1, &vao);
         gl::DeleteBuffers(1, &vbo);
         gl::DeleteBuffers(1, &ebo);
+        for material in &objdata.materials {
+            if let Some(texture_id) = material.diffuse_texture_id {
+                gl::DeleteTextures(1, &texture_id);
+            }
+        }
         gl::DeleteProgram(glvar.shader_prgm_id);
     }
     Ok(())
 }
+
+// only meshes parsed without any warning are cached, so that the warnings are shown again on the next run
+// and a file that needed repairs is still refused in strict mode
+fn parse_and_cache(loader: &dyn MeshLoader, file_path: &str, options: &ParseOptions, use_cache: bool) -> Result<ObjData, String> {
+    match loader.load(file_path, options) {
+        Ok((data, warnings)) => {
+            for warning in &warnings {
+                println!("Warning: {}", warning);
+            }
+            if use_cache && warnings.is_empty() {
+                if let Err(err) = mesh_cache::write_cached_mesh(file_path, loader.name(), options.resolution, &data) {
+                    println!("Warning: could not write the mesh cache: {}", err);
+                }
+            }
+            Ok(data)
+        }
+        Err(err) => {
+            eprint!("{}", err.diagnostic());
+            Err(format!("Error while parsing mesh file: {}", err))
+        }
+    }
+}
+
+fn parse_positive_argument<T: FromStr + PartialOrd + Default>(flag: &str, value: Option<String>, usage: &str) -> Result<T, String> {
+    match value.as_deref().map(str::parse::<T>) {
+        Some(Ok(value)) if value > T::default() => Ok(value),
+        _ => Err(format!("Error: {} expects a positive number\n{}", flag, usage)),
+    }
+}
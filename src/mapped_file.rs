@@ -0,0 +1,82 @@
+use std::io::Error;
+use std::ops::Deref;
+
+// read-only view of the whole content of a file. it is memory-mapped on 64-bit unix so that large scans
+// don't have to be copied before parsing, and simply read into memory elsewhere.
+// the file must not be truncated while it is mapped
+pub struct MappedFile {
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    map: Option<(*const u8, usize)>,
+    bytes: Vec<u8>,
+}
+
+// the raw pointer makes the type neither Send nor Sync by default. the mapping is read-only and private,
+// it is only ever seen through the shared slice given by deref, which can't outlive the MappedFile,
+// and munmap only runs once in drop. so moving it to another thread or reading it from several
+// threads at once is as safe as doing so with a &[u8]
+#[cfg(all(unix, target_pointer_width = "64"))]
+unsafe impl Send for MappedFile {}
+#[cfg(all(unix, target_pointer_width = "64"))]
+unsafe impl Sync for MappedFile {}
+
+// declared here rather than pulling in libc for two functions. off_t is 64 bits on every 64-bit unix,
+// 32-bit targets read the file instead
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod ffi {
+    use std::os::raw::{c_int, c_void};
+
+    pub const PROT_READ: c_int = 1;
+    pub const MAP_PRIVATE: c_int = 2;
+
+    extern "C" {
+        pub fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+impl MappedFile {
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    pub fn open(file_path: &str) -> Result<Self, Error> {
+        use std::fs::File;
+        use std::os::unix::io::AsRawFd;
+
+        let file = File::open(file_path)?;
+        let len = file.metadata()?.len() as usize;
+        // mapping an empty file fails, there is nothing to map anyway
+        if len == 0 {
+            return Ok(MappedFile {map: None, bytes: Vec::new()});
+        }
+        let ptr = unsafe { ffi::mmap(std::ptr::null_mut(), len, ffi::PROT_READ, ffi::MAP_PRIVATE, file.as_raw_fd(), 0) };
+        if ptr as isize == -1 {
+            // some file systems can't be mapped, read them instead
+            return Ok(MappedFile {map: None, bytes: std::fs::read(file_path)?});
+        }
+        Ok(MappedFile {map: Some((ptr as *const u8, len)), bytes: Vec::new()})
+    }
+
+    #[cfg(not(all(unix, target_pointer_width = "64")))]
+    pub fn open(file_path: &str) -> Result<Self, Error> {
+        Ok(MappedFile {bytes: std::fs::read(file_path)?})
+    }
+}
+
+impl Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        if let Some((ptr, len)) = self.map {
+            return unsafe { std::slice::from_raw_parts(ptr, len) };
+        }
+        &self.bytes
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        if let Some((ptr, len)) = self.map {
+            unsafe { ffi::munmap(ptr as *mut _, len) };
+        }
+    }
+}
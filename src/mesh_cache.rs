@@ -0,0 +1,377 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Error;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use gl::types::GLuint;
+
+use crate::models::material::{Material, MaterialRange};
+use crate::models::obj_data::{ObjData, Vertex};
+use crate::models::sub_mesh::SubMesh;
+use crate::models::vec3::Vec3;
+
+// binary copy of a parsed mesh, stored in the user cache directory so that a file is only parsed again
+// when it changed. the layout, all little endian:
+//  - header: magic, version, then the key (source path, size, mtime, name of the loader and tessellation resolution)
+//    followed by the same size and mtime for each file the mesh depends on
+//  - vertex layout: the number of floats of each attribute, then whether the file had vertex colours
+//  - interleaved vertex data as in ObjData::vertices_raw, then the names and values of the extra vertex properties
+//  - triangle, line and point indices, then the smoothing group of each triangle
+//  - sub-mesh, material range and material tables
+// a cache that doesn't match the source or can't be read is ignored and written again
+const MAGIC: &[u8; 8] = b"SCOPMESH";
+// to be bumped whenever the layout above or the meaning of a parsed mesh changes
+const VERSION: u32 = 6;
+// position, colour, texture coordinates and normal
+const VERTEX_LAYOUT: [u32; 4] = [3, 3, 2, 3];
+const NO_MATERIAL: u32 = u32::MAX;
+
+// the loader is part of the key since --format can read the same file as another format
+pub fn load_cached_mesh(file_path: &str, loader: &str, resolution: u32) -> Option<ObjData> {
+    let bytes = fs::read(cache_path(file_path)?).ok()?;
+    let mut reader = Reader {bytes: &bytes};
+    if reader.take(MAGIC.len())? != MAGIC || reader.u32()? != VERSION {
+        return None;
+    }
+    if reader.key()? != source_key(file_path)? || reader.string()? != loader || reader.u32()? != resolution {
+        return None;
+    }
+    let mut dependencies = Vec::new();
+    for _ in 0..reader.u32()? {
+        let key = reader.key()?;
+        if Some(&key) != source_key(&key.0).as_ref() {
+            return None;
+        }
+        dependencies.push(key.0);
+    }
+    if (0..VERTEX_LAYOUT.len()).map(|_| reader.u32()).collect::<Option<Vec<u32>>>()? != VERTEX_LAYOUT {
+        return None;
+    }
+    let has_vertex_colors = reader.u32()? != 0;
+
+    let num_vertices = reader.u32()? as usize;
+    let vertex_data = reader.f32s(num_vertices.checked_mul(11)?)?;
+    let vertex_properties = (0..reader.u32()?).map(|_| reader.string()).collect::<Option<Vec<String>>>()?;
+    let property_data = reader.f32s(num_vertices.checked_mul(vertex_properties.len())?)?;
+    let vertices = vertex_data.chunks(11)
+        .enumerate()
+        .map(|(i, v)| Vertex {
+            position: Vec3::new(v[0], v[1], v[2]),
+            rgb: Vec3::new(v[3], v[4], v[5]),
+            text_x: v[6],
+            text_y: v[7],
+            normal: Vec3::new(v[8], v[9], v[10]),
+            properties: property_data[i * vertex_properties.len()..(i + 1) * vertex_properties.len()].to_vec(),
+        })
+        .collect();
+    let num_indices = reader.u32()? as usize;
+    let indices = reader.u32s(num_indices)?;
+    let num_line_indices = reader.u32()? as usize;
+    let line_indices = reader.u32s(num_line_indices)?;
+    let num_point_indices = reader.u32()? as usize;
+    let point_indices = reader.u32s(num_point_indices)?;
+    let smoothing_groups = reader.u32s(num_indices / 3)?;
+
+    let mut sub_meshes = Vec::new();
+    for _ in 0..reader.u32()? {
+        let mut sub_mesh = SubMesh::new(&reader.string()?, &reader.string()?, reader.u32()? as usize);
+        sub_mesh.num_indices = reader.u32()? as usize;
+        sub_meshes.push(sub_mesh);
+    }
+    let mut material_ranges = Vec::new();
+    for _ in 0..reader.u32()? {
+        let material = Some(reader.u32()?).filter(|&material| material != NO_MATERIAL).map(|material| material as usize);
+        material_ranges.push(MaterialRange {material, first_index: reader.u32()? as usize, num_indices: reader.u32()? as usize});
+    }
+    let mut materials = Vec::new();
+    for _ in 0..reader.u32()? {
+        materials.push(reader.material()?);
+    }
+    if !reader.bytes.is_empty() || material_ranges.iter().any(|range| range.material.is_some_and(|material| material >= materials.len())) {
+        return None;
+    }
+
+    let mut obj_data = ObjData::new(vertices, indices, smoothing_groups);
+    obj_data.materials = materials;
+    obj_data.dependencies = dependencies;
+    obj_data.material_ranges = material_ranges;
+    obj_data.sub_meshes = sub_meshes;
+    obj_data.has_vertex_colors = has_vertex_colors;
+    obj_data.vertex_properties = vertex_properties;
+    obj_data.set_lines_and_points(line_indices, point_indices);
+    Some(obj_data)
+}
+
+// to be given the mesh straight out of the parser, before it is validated or processed
+pub fn write_cached_mesh(file_path: &str, loader: &str, resolution: u32, obj_data: &ObjData) -> Result<(), Error> {
+    let missing = || Error::new(std::io::ErrorKind::NotFound, "no cache directory or unreadable source file");
+    let path = cache_path(file_path).ok_or_else(missing)?;
+    let mut out = Vec::with_capacity(obj_data.vertex_buffer_size + obj_data.indices.len() * 4 + 1024);
+
+    out.extend(MAGIC);
+    put_u32(&mut out, VERSION);
+    put_key(&mut out, &source_key(file_path).ok_or_else(missing)?);
+    put_string(&mut out, loader);
+    put_u32(&mut out, resolution);
+    put_u32(&mut out, obj_data.dependencies.len() as u32);
+    for dependency in &obj_data.dependencies {
+        put_key(&mut out, &source_key(dependency).ok_or_else(missing)?);
+    }
+    VERTEX_LAYOUT.iter().for_each(|&size| put_u32(&mut out, size));
+    put_u32(&mut out, obj_data.has_vertex_colors as u32);
+
+    put_u32(&mut out, obj_data.num_vertices);
+    obj_data.vertices_raw.iter().for_each(|&value| put_f32(&mut out, value));
+    put_u32(&mut out, obj_data.vertex_properties.len() as u32);
+    obj_data.vertex_properties.iter().for_each(|name| put_string(&mut out, name));
+    obj_data.vertices.iter().flat_map(|vertex| &vertex.properties).for_each(|&value| put_f32(&mut out, value));
+    put_u32(&mut out, obj_data.num_indices as u32);
+    (0..obj_data.num_indices).for_each(|i| put_u32(&mut out, obj_data.indices.get(i)));
+    for indices in [&obj_data.line_indices, &obj_data.point_indices] {
+        put_u32(&mut out, indices.len() as u32);
+        indices.iter().for_each(|&index| put_u32(&mut out, index));
+    }
+    obj_data.smoothing_groups.iter().for_each(|&group| put_u32(&mut out, group));
+
+    put_u32(&mut out, obj_data.sub_meshes.len() as u32);
+    for sub_mesh in &obj_data.sub_meshes {
+        put_string(&mut out, &sub_mesh.object);
+        put_string(&mut out, &sub_mesh.group);
+        put_u32(&mut out, sub_mesh.first_index as u32);
+        put_u32(&mut out, sub_mesh.num_indices as u32);
+    }
+    put_u32(&mut out, obj_data.material_ranges.len() as u32);
+    for range in &obj_data.material_ranges {
+        put_u32(&mut out, range.material.map_or(NO_MATERIAL, |material| material as u32));
+        put_u32(&mut out, range.first_index as u32);
+        put_u32(&mut out, range.num_indices as u32);
+    }
+    put_u32(&mut out, obj_data.materials.len() as u32);
+    for material in &obj_data.materials {
+        put_material(&mut out, material);
+    }
+
+    fs::create_dir_all(path.parent().unwrap_or(&path))?;
+    // written aside then renamed, so that an interrupted write never leaves a truncated cache behind
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, out)?;
+    fs::rename(temporary, path)
+}
+
+// canonical path, size and modification time (seconds and nanoseconds) of a file
+type SourceKey = (String, u64, u64, u32);
+
+fn source_key(file_path: &str) -> Option<SourceKey> {
+    let path = fs::canonicalize(file_path).ok()?;
+    let metadata = fs::metadata(&path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((path.to_string_lossy().into_owned(), metadata.len(), modified.as_secs(), modified.subsec_nanos()))
+}
+
+// $XDG_CACHE_HOME/scop or ~/.cache/scop, one file per source named after a hash of its path.
+// the full key is in the header, so a hash collision is only a cache miss
+fn cache_path(file_path: &str) -> Option<PathBuf> {
+    let directory = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(directory) if !directory.is_empty() => PathBuf::from(directory),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    let mut hasher = DefaultHasher::new();
+    fs::canonicalize(file_path).ok()?.hash(&mut hasher);
+    Some(directory.join("scop").join(format!("{:016x}.mesh", hasher.finish())))
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend(value.to_le_bytes());
+}
+
+fn put_f32(out: &mut Vec<u8>, value: f32) {
+    out.extend(value.to_le_bytes());
+}
+
+fn put_string(out: &mut Vec<u8>, value: &str) {
+    put_u32(out, value.len() as u32);
+    out.extend(value.as_bytes());
+}
+
+fn put_key(out: &mut Vec<u8>, (path, size, seconds, nanoseconds): &SourceKey) {
+    put_string(out, path);
+    out.extend(size.to_le_bytes());
+    out.extend(seconds.to_le_bytes());
+    put_u32(out, *nanoseconds);
+}
+
+fn put_material(out: &mut Vec<u8>, material: &Material) {
+    put_string(out, &material.name);
+    for color in [material.ambient, material.diffuse, material.specular, material.emissive] {
+        [color.x, color.y, color.z].iter().for_each(|&value| put_f32(out, value));
+    }
+    put_f32(out, material.shininess);
+    put_f32(out, material.dissolve);
+    put_f32(out, material.optical_density);
+    put_u32(out, material.illum);
+    for map in [&material.ambient_map, &material.diffuse_map, &material.specular_map, &material.dissolve_map, &material.bump_map] {
+        put_u32(out, map.is_some() as u32);
+        put_string(out, map.as_deref().unwrap_or(""));
+    }
+    put_u32(out, material.diffuse_image.is_some() as u32);
+    let image = material.diffuse_image.as_deref().unwrap_or(&[]);
+    put_u32(out, image.len() as u32);
+    out.extend(image);
+}
+
+// reads the values back in the same order, none once the data runs out
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u32s(&mut self, count: usize) -> Option<Vec<GLuint>> {
+        let bytes = self.take(count.checked_mul(4)?)?;
+        Some(bytes.chunks(4).map(|value| u32::from_le_bytes(value.try_into().unwrap())).collect())
+    }
+
+    fn f32s(&mut self, count: usize) -> Option<Vec<f32>> {
+        let bytes = self.take(count.checked_mul(4)?)?;
+        Some(bytes.chunks(4).map(|value| f32::from_le_bytes(value.try_into().unwrap())).collect())
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    fn key(&mut self) -> Option<SourceKey> {
+        Some((self.string()?, self.u64()?, self.u64()?, self.u32()?))
+    }
+
+    fn vec3(&mut self) -> Option<Vec3> {
+        Some(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    fn material(&mut self) -> Option<Material> {
+        let mut material = Material::new(&self.string()?);
+        material.ambient = self.vec3()?;
+        material.diffuse = self.vec3()?;
+        material.specular = self.vec3()?;
+        material.emissive = self.vec3()?;
+        material.shininess = self.f32()?;
+        material.dissolve = self.f32()?;
+        material.optical_density = self.f32()?;
+        material.illum = self.u32()?;
+        for map in [&mut material.ambient_map, &mut material.diffuse_map, &mut material.specular_map, &mut material.dissolve_map, &mut material.bump_map] {
+            let present = self.u32()? != 0;
+            let path = self.string()?;
+            *map = Some(path).filter(|_| present);
+        }
+        let present = self.u32()? != 0;
+        let len = self.u32()? as usize;
+        let image = self.take(len)?.to_vec();
+        material.diffuse_image = Some(image).filter(|_| present);
+        Some(material)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    use crate::obj_parser::parse_obj_file;
+    use crate::test_support::{test_path, write_test_file, LENIENT};
+
+    const SOURCE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.5 0.5\nusemtl a\nf 1/1 2/1 3/1\nl 1 2\np 3\n";
+
+    // every test sets the same directory, so running them in parallel doesn't matter.
+    // each source has its own material library, as changing one would miss the cache of the others
+    fn cache_source(name: &str) -> (String, ObjData) {
+        std::env::set_var("XDG_CACHE_HOME", test_path("cache"));
+        write_test_file(&format!("{}.mtl", name), b"newmtl a\nKd 1 0 0\n");
+        let path = write_test_file(&format!("{}.obj", name), format!("mtllib {}.mtl\n{}", name, SOURCE).as_bytes());
+        let (obj_data, _) = parse_obj_file(&path, &LENIENT).unwrap();
+        write_cached_mesh(&path, "obj", 8, &obj_data).unwrap();
+        (path, obj_data)
+    }
+
+    fn set_modified(path: &str, time: SystemTime) {
+        fs::File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+    }
+
+    #[test]
+    fn cached_meshes_read_back_the_same() {
+        let (path, obj_data) = cache_source("cache-round-trip");
+        let cached = load_cached_mesh(&path, "obj", 8).unwrap();
+        assert_eq!(cached.vertices_raw, obj_data.vertices_raw);
+        let indices = |obj_data: &ObjData| (0..obj_data.indices.len()).map(|i| obj_data.indices.get(i)).collect::<Vec<_>>();
+        assert_eq!(indices(&cached), indices(&obj_data));
+        assert_eq!((cached.num_indices, cached.line_indices.len(), cached.point_indices.len()), (3, 2, 1));
+        assert_eq!(cached.materials[0].to_string(), obj_data.materials[0].to_string());
+        assert_eq!(cached.dependencies, obj_data.dependencies);
+    }
+
+    #[test]
+    fn other_loaders_and_resolutions_miss() {
+        let (path, _) = cache_source("cache-key");
+        assert!(load_cached_mesh(&path, "obj", 8).is_some());
+        assert!(load_cached_mesh(&path, "stl", 8).is_none());
+        assert!(load_cached_mesh(&path, "obj", 16).is_none());
+    }
+
+    #[test]
+    fn changed_sources_miss() {
+        let (path, _) = cache_source("cache-size");
+        fs::write(&path, format!("mtllib cache-size.mtl\n{}v 1 1 1\n", SOURCE)).unwrap();
+        assert!(load_cached_mesh(&path, "obj", 8).is_none());
+
+        // same content, only the modification time moves
+        let (path, _) = cache_source("cache-mtime");
+        set_modified(&path, SystemTime::now() + Duration::from_secs(10));
+        assert!(load_cached_mesh(&path, "obj", 8).is_none());
+    }
+
+    #[test]
+    fn changed_dependencies_miss() {
+        let (path, obj_data) = cache_source("cache-mtl");
+        assert!(load_cached_mesh(&path, "obj", 8).is_some());
+        set_modified(&obj_data.dependencies[0], SystemTime::now() + Duration::from_secs(20));
+        assert!(load_cached_mesh(&path, "obj", 8).is_none());
+    }
+
+    #[test]
+    fn damaged_cache_files_are_ignored() {
+        let (path, _) = cache_source("cache-damaged");
+        let cache = cache_path(&path).unwrap();
+        let bytes = fs::read(&cache).unwrap();
+        for len in (0..bytes.len()).step_by(3) {
+            fs::write(&cache, &bytes[..len]).unwrap();
+            assert!(load_cached_mesh(&path, "obj", 8).is_none(), "truncated to {}", len);
+        }
+        fs::write(&cache, [bytes.as_slice(), &[0]].concat()).unwrap();
+        assert!(load_cached_mesh(&path, "obj", 8).is_none());
+        let mut version = bytes.clone();
+        version[MAGIC.len()] += 1;
+        fs::write(&cache, version).unwrap();
+        assert!(load_cached_mesh(&path, "obj", 8).is_none());
+    }
+}
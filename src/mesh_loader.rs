@@ -0,0 +1,237 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::gltf_parser;
+use crate::models::obj_data::ObjData;
+use crate::models::obj_parse_error::ObjParseError;
+use crate::models::parse_options::ParseOptions;
+use crate::obj_parser;
+use crate::off_parser;
+use crate::ply_parser;
+use crate::stl_parser;
+
+// how much of the start of a file is read to recognise its format
+const SNIFF_SIZE: u64 = 4096;
+
+// a mesh file format. a new format only needs a loader added to LoaderRegistry::new
+pub trait MeshLoader {
+    // the name given to --format
+    fn name(&self) -> &'static str;
+
+    // lowercase, used when the content doesn't tell
+    fn extensions(&self) -> &'static [&'static str];
+
+    // whether the start of the file looks like this format, file_size is the size of the whole file
+    fn sniff(&self, head: &[u8], file_size: u64) -> bool;
+
+    fn load(&self, file_path: &str, options: &ParseOptions) -> Result<(ObjData, Vec<ObjParseError>), ObjParseError>;
+}
+
+pub struct ObjLoader;
+pub struct StlLoader;
+pub struct PlyLoader;
+pub struct OffLoader;
+pub struct GltfLoader;
+
+impl MeshLoader for ObjLoader {
+    fn name(&self) -> &'static str {
+        "obj"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["obj"]
+    }
+
+    fn sniff(&self, head: &[u8], _file_size: u64) -> bool {
+        obj_parser::looks_like_obj(head)
+    }
+
+    fn load(&self, file_path: &str, options: &ParseOptions) -> Result<(ObjData, Vec<ObjParseError>), ObjParseError> {
+        obj_parser::parse_obj_file(file_path, options)
+    }
+}
+
+impl MeshLoader for StlLoader {
+    fn name(&self) -> &'static str {
+        "stl"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["stl"]
+    }
+
+    fn sniff(&self, head: &[u8], file_size: u64) -> bool {
+        stl_parser::looks_like_stl(head, file_size)
+    }
+
+    fn load(&self, file_path: &str, options: &ParseOptions) -> Result<(ObjData, Vec<ObjParseError>), ObjParseError> {
+        stl_parser::parse_stl_file(file_path, options)
+    }
+}
+
+impl MeshLoader for PlyLoader {
+    fn name(&self) -> &'static str {
+        "ply"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ply"]
+    }
+
+    fn sniff(&self, head: &[u8], _file_size: u64) -> bool {
+        ply_parser::looks_like_ply(head)
+    }
+
+    fn load(&self, file_path: &str, options: &ParseOptions) -> Result<(ObjData, Vec<ObjParseError>), ObjParseError> {
+        ply_parser::parse_ply_file(file_path, options)
+    }
+}
+
+impl MeshLoader for OffLoader {
+    fn name(&self) -> &'static str {
+        "off"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["off"]
+    }
+
+    fn sniff(&self, head: &[u8], _file_size: u64) -> bool {
+        off_parser::looks_like_off(head)
+    }
+
+    fn load(&self, file_path: &str, options: &ParseOptions) -> Result<(ObjData, Vec<ObjParseError>), ObjParseError> {
+        off_parser::parse_off_file(file_path, options)
+    }
+}
+
+impl MeshLoader for GltfLoader {
+    fn name(&self) -> &'static str {
+        "gltf"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["gltf", "glb"]
+    }
+
+    fn sniff(&self, head: &[u8], _file_size: u64) -> bool {
+        gltf_parser::looks_like_gltf(head)
+    }
+
+    fn load(&self, file_path: &str, options: &ParseOptions) -> Result<(ObjData, Vec<ObjParseError>), ObjParseError> {
+        gltf_parser::parse_gltf_file(file_path, options)
+    }
+}
+
+pub struct LoaderRegistry {
+    loaders: Vec<Box<dyn MeshLoader>>,
+}
+
+impl LoaderRegistry {
+    // formats with a distinctive signature come first, obj has the loosest one and is also
+    // what files nothing recognises are read as
+    pub fn new() -> Self {
+        LoaderRegistry {
+            loaders: vec![
+                Box::new(GltfLoader),
+                Box::new(PlyLoader),
+                Box::new(StlLoader),
+                Box::new(OffLoader),
+                Box::new(ObjLoader),
+            ],
+        }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.loaders.iter().map(|loader| loader.name()).collect()
+    }
+
+    pub fn find(&self, name: &str) -> Option<&dyn MeshLoader> {
+        self.loaders.iter().find(|loader| loader.name() == name.to_lowercase()).map(|loader| loader.as_ref())
+    }
+
+    // the content decides first, then the extension. a file that can't be read goes to the loader of its
+    // extension, which reports the error
+    pub fn detect(&self, file_path: &str) -> &dyn MeshLoader {
+        if let Some((head, file_size)) = read_head(file_path) {
+            if let Some(loader) = self.loaders.iter().find(|loader| loader.sniff(&head, file_size)) {
+                return loader.as_ref();
+            }
+        }
+        let extension = Path::new(file_path).extension().map(|extension| extension.to_string_lossy().to_lowercase());
+        let by_extension = self.loaders.iter().find(|loader| extension.as_deref().is_some_and(|extension| loader.extensions().contains(&extension)));
+        match by_extension {
+            Some(loader) => loader.as_ref(),
+            None => self.find("obj").unwrap(),
+        }
+    }
+}
+
+fn read_head(file_path: &str) -> Option<(Vec<u8>, u64)> {
+    let file = File::open(file_path).ok()?;
+    let file_size = file.metadata().ok()?.len();
+    let mut head = Vec::new();
+    file.take(SNIFF_SIZE).read_to_end(&mut head).ok()?;
+    Some((head, file_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{test_path, write_test_file};
+
+    // an 80 byte header, the triangle count then one triangle of 50 bytes
+    fn binary_stl(header: &[u8]) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        bytes.resize(80, b' ');
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend([0; 50]);
+        bytes
+    }
+
+    #[test]
+    fn content_comes_before_the_extension() {
+        let registry = LoaderRegistry::new();
+        let cases: [(&str, Vec<u8>, &str); 7] = [
+            ("loader-ply.obj", b"ply\r\nformat ascii 1.0\r\n".to_vec(), "ply"),
+            ("loader-off.txt", b"# geomview\nCOFF\n3 1 0\n".to_vec(), "off"),
+            ("loader-gltf.obj", b"\n  {\"asset\": {\"version\": \"2.0\"}}".to_vec(), "gltf"),
+            ("loader-glb.stl", b"glTF\x02\x00\x00\x00".to_vec(), "gltf"),
+            ("loader-ascii.dat", b"solid cube\nendsolid cube\n".to_vec(), "stl"),
+            ("loader-obj.mesh", b"# exported\n\nv 0 0 0\n".to_vec(), "obj"),
+            // obj is sniffed last, so a binary stl whose header reads like obj is still an stl
+            ("loader-binary.obj", binary_stl(b"v 0 0 0 made by an exporter"), "stl"),
+        ];
+        for (name, contents, expected) in cases {
+            assert_eq!(registry.detect(&write_test_file(name, &contents)).name(), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn the_extension_decides_when_the_content_doesnt() {
+        let registry = LoaderRegistry::new();
+        // the size doesn't match the triangle count and the header isn't ascii
+        let mut broken_stl = binary_stl(b"\x00binary");
+        broken_stl.truncate(100);
+        assert_eq!(registry.detect(&write_test_file("loader-broken.STL", &broken_stl)).name(), "stl");
+        assert_eq!(registry.detect(&write_test_file("loader-unknown.Off", b"nothing known\n")).name(), "off");
+        assert_eq!(registry.detect(&write_test_file("loader-unknown.glb", b"")).name(), "gltf");
+        // unreadable files are reported by the loader of their extension
+        assert_eq!(registry.detect(&test_path("loader-missing.ply").to_string_lossy()).name(), "ply");
+        // and obj takes whatever is left
+        assert_eq!(registry.detect(&write_test_file("loader-unknown.xyz", b"1 2 3\n")).name(), "obj");
+        assert_eq!(registry.detect(&test_path("loader-missing").to_string_lossy()).name(), "obj");
+    }
+
+    #[test]
+    fn formats_by_name() {
+        let registry = LoaderRegistry::new();
+        assert_eq!(registry.names(), ["gltf", "ply", "stl", "off", "obj"]);
+        for name in registry.names() {
+            assert_eq!(registry.find(&name.to_uppercase()).unwrap().name(), name);
+        }
+        assert!(registry.find("glb").is_none());
+        assert!(registry.find("fbx").is_none());
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use gl::types::GLuint;
+
+use crate::models::obj_data::{ObjData, Vertex};
+use crate::models::vec3::Vec3;
+
+// one normal per triangle, its length is twice the triangle area
+fn unnormalized_face_normal(obj_data: &ObjData, triangle: usize) -> Vec3 {
+    let a = obj_data.vertices[obj_data.indices.get(triangle * 3) as usize].position;
+    let b = obj_data.vertices[obj_data.indices.get(triangle * 3 + 1) as usize].position;
+    let c = obj_data.vertices[obj_data.indices.get(triangle * 3 + 2) as usize].position;
+    b.sub(a).cross(c.sub(a))
+}
+
+pub fn compute_face_normals(obj_data: &ObjData) -> Vec<Vec3> {
+    (0..obj_data.num_indices / 3)
+        .map(|triangle| safe_normalize(unnormalized_face_normal(obj_data, triangle)))
+        .collect()
+}
+
+// fills in the normal of every vertex that has none (zero vector), vertices that already
+// have a normal are left untouched.
+// a corner is smoothed with the other faces sharing its position only if they are in the same
+// smoothing group (0 means flat) and their normals are less than crease_angle degrees apart.
+// each face contributes its normal weighted by its area and by its angle at the shared corner.
+pub fn generate_missing_normals(obj_data: &mut ObjData, crease_angle: f32) -> Result<(), Error> {
+    if obj_data.vertices.iter().all(|vertex| vertex.normal.length() != 0.0) {
+        return Ok(());
+    }
+
+    let num_triangles = obj_data.num_indices / 3;
+    let face_normals = compute_face_normals(obj_data);
+    let cos_crease = crease_angle.to_radians().cos();
+
+    // corners are grouped by position rather than by vertex so that uv seams don't break the smoothing
+    let mut corners_by_position: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+    for corner in 0..obj_data.num_indices {
+        let key = position_key(obj_data.vertices[obj_data.indices.get(corner) as usize].position);
+        corners_by_position.entry(key).or_default().push(corner);
+    }
+
+    let corner_weights: Vec<f32> = (0..obj_data.num_indices)
+        .map(|corner| corner_weight(obj_data, corner))
+        .collect();
+
+    let mut vertices: Vec<Vertex> = Vec::new();
+    let mut indices: Vec<GLuint> = Vec::with_capacity(obj_data.num_indices);
+    let mut unique: HashMap<(GLuint, [u32; 3]), GLuint> = HashMap::new();
+    // lines and points use the first copy of a vertex split between several normals
+    let mut first_copy: Vec<Option<GLuint>> = vec![None; obj_data.vertices.len()];
+
+    for corner in 0..num_triangles * 3 {
+        let index = obj_data.indices.get(corner);
+        let vertex = &obj_data.vertices[index as usize];
+        let triangle = corner / 3;
+
+        let normal = if vertex.normal.length() != 0.0 {
+            vertex.normal
+        } else if obj_data.smoothing_groups[triangle] == 0 {
+            face_normals[triangle]
+        } else {
+            let mut sum = Vec3::new(0.0, 0.0, 0.0);
+            for &other in &corners_by_position[&position_key(vertex.position)] {
+                let other_triangle = other / 3;
+                if obj_data.smoothing_groups[other_triangle] != obj_data.smoothing_groups[triangle] {
+                    continue;
+                }
+                if face_normals[other_triangle].dot(face_normals[triangle]) < cos_crease {
+                    continue;
+                }
+                sum = sum.add(face_normals[other_triangle].scale(corner_weights[other]));
+            }
+            if sum.length() == 0.0 { face_normals[triangle] } else { sum.normalize() }
+        };
+
+        let new_index = match unique.get(&(index, position_key(normal))) {
+            Some(&new_index) => new_index,
+            None => {
+                let new_index = vertices.len() as GLuint;
+                let mut new_vertex = vertex.clone();
+                new_vertex.normal = normal;
+                vertices.push(new_vertex);
+                unique.insert((index, position_key(normal)), new_index);
+                first_copy[index as usize].get_or_insert(new_index);
+                new_index
+            }
+        };
+        indices.push(new_index);
+    }
+
+    obj_data.remap_lines_and_points(&mut first_copy, &mut vertices);
+    obj_data.set_geometry(vertices, indices);
+    Ok(())
+}
+
+// merges the vertices whose positions are less than epsilon apart, as long as their texture coordinates
+// and normals (and colours when the file gave them) are also within epsilon of each other, so uv seams
+// and hard edges survive. every vertex is merged into the first matching one, which keeps its attributes.
+// returns the number of vertices removed
+pub fn weld_vertices(obj_data: &mut ObjData, epsilon: f32) -> usize {
+    // vertices are bucketed in a grid of epsilon sized cells, a match can only be in a neighbouring cell
+    let cell_of = |position: Vec3| -> [i64; 3] {
+        [position.x, position.y, position.z].map(|value| (value / epsilon).floor() as i64)
+    };
+    let mut grid: HashMap<[i64; 3], Vec<GLuint>> = HashMap::new();
+    let mut vertices: Vec<Vertex> = Vec::new();
+    let mut remap: Vec<GLuint> = Vec::with_capacity(obj_data.vertices.len());
+
+    for vertex in &obj_data.vertices {
+        let cell = cell_of(vertex.position);
+        let mut found = None;
+        'search: for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let Some(candidates) = grid.get(&[cell[0].saturating_add(dx), cell[1].saturating_add(dy), cell[2].saturating_add(dz)]) else { continue };
+                    found = candidates.iter().copied()
+                        .find(|&candidate| can_weld(&vertices[candidate as usize], vertex, epsilon, obj_data.has_vertex_colors));
+                    if found.is_some() {
+                        break 'search;
+                    }
+                }
+            }
+        }
+        let index = found.unwrap_or_else(|| {
+            let index = vertices.len() as GLuint;
+            vertices.push(vertex.clone());
+            grid.entry(cell).or_default().push(index);
+            index
+        });
+        remap.push(index);
+    }
+
+    let removed = obj_data.vertices.len() - vertices.len();
+    if removed == 0 {
+        return 0;
+    }
+    let indices = (0..obj_data.num_indices).map(|i| remap[obj_data.indices.get(i) as usize]).collect();
+    obj_data.line_indices.iter_mut().for_each(|index| *index = remap[*index as usize]);
+    obj_data.point_indices.iter_mut().for_each(|index| *index = remap[*index as usize]);
+    obj_data.set_geometry(vertices, indices);
+    removed
+}
+
+fn can_weld(kept: &Vertex, vertex: &Vertex, epsilon: f32, compare_colors: bool) -> bool {
+    kept.position.sub(vertex.position).length() <= epsilon
+        && (kept.text_x - vertex.text_x).abs() <= epsilon
+        && (kept.text_y - vertex.text_y).abs() <= epsilon
+        && kept.normal.sub(vertex.normal).length() <= epsilon
+        && (!compare_colors || kept.rgb.sub(vertex.rgb).length() <= epsilon)
+}
+
+// shows one of the extra vertex properties as colours, from blue at its lowest value to red at its highest
+pub fn color_by_property(obj_data: &mut ObjData, name: &str) -> Result<(), Error> {
+    let Some(property) = obj_data.vertex_properties.iter().position(|property| property == name) else {
+        let available = if obj_data.vertex_properties.is_empty() { "none".to_string() } else { obj_data.vertex_properties.join(", ") };
+        return Err(Error::new(ErrorKind::NotFound, format!("no vertex property named '{}' (available: {})", name, available)));
+    };
+    let values = obj_data.vertices.iter().map(|vertex| vertex.properties[property]).filter(|value| value.is_finite());
+    let (low, high) = values.fold((f32::MAX, f32::MIN), |(low, high), value| (low.min(value), high.max(value)));
+    let range = if high > low { high - low } else { 1.0 };
+
+    let mut vertices = std::mem::take(&mut obj_data.vertices);
+    for vertex in vertices.iter_mut() {
+        let value = vertex.properties[property];
+        vertex.rgb = if value.is_finite() { color_ramp((value - low) / range) } else { Vec3::new(0.5, 0.5, 0.5) };
+    }
+    let indices = (0..obj_data.num_indices).map(|i| obj_data.indices.get(i)).collect();
+    obj_data.set_geometry(vertices, indices);
+    obj_data.has_vertex_colors = true;
+    Ok(())
+}
+
+// blue, cyan, green, yellow then red as t goes from 0 to 1
+fn color_ramp(t: f32) -> Vec3 {
+    let stops = [
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec3::new(0.0, 1.0, 1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::new(1.0, 0.0, 0.0),
+    ];
+    let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let segment = (position as usize).min(stops.len() - 2);
+    let local = position - segment as f32;
+    stops[segment].scale(1.0 - local).add(stops[segment + 1].scale(local))
+}
+
+fn corner_weight(obj_data: &ObjData, corner: usize) -> f32 {
+    let triangle = corner / 3;
+    let first = triangle * 3;
+    let position = |i: usize| obj_data.vertices[obj_data.indices.get(first + i % 3) as usize].position;
+    let local = corner - first;
+    let edge1 = position(local + 1).sub(position(local));
+    let edge2 = position(local + 2).sub(position(local));
+    let length_product = edge1.length() * edge2.length();
+    if length_product == 0.0 {
+        return 0.0;
+    }
+    let angle = (edge1.dot(edge2) / length_product).clamp(-1.0, 1.0).acos();
+    let area = unnormalized_face_normal(obj_data, triangle).length() / 2.0;
+    angle * area
+}
+
+fn safe_normalize(v: Vec3) -> Vec3 {
+    if v.length() == 0.0 { v } else { v.normalize() }
+}
+
+fn position_key(v: Vec3) -> [u32; 3] {
+    // adding 0.0 turns -0.0 into 0.0 so both hash the same
+    [(v.x + 0.0).to_bits(), (v.y + 0.0).to_bits(), (v.z + 0.0).to_bits()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::globals::CREASE_ANGLE;
+    use crate::obj_parser::parse_obj_file;
+    use crate::test_support::{write_test_file, STRICT};
+
+    // two triangles folded along the edge 2-3, the fourth corner is lifted by height
+    fn folded_quad(name: &str, height: f32, smoothing: &str) -> ObjData {
+        let contents = format!("v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 {}\n{}f 1 2 3\nf 2 4 3\n", height, smoothing);
+        let (mut obj_data, _) = parse_obj_file(&write_test_file(name, contents.as_bytes()), &STRICT).unwrap();
+        generate_missing_normals(&mut obj_data, CREASE_ANGLE).unwrap();
+        obj_data
+    }
+
+    // the normals the two triangles use at the corners of their shared edge
+    fn shared_edge_normals(obj_data: &ObjData) -> [(Vec3, Vec3); 2] {
+        let normal = |corner: usize| obj_data.vertices[obj_data.indices.get(corner) as usize].normal;
+        [(normal(1), normal(3)), (normal(2), normal(5))]
+    }
+
+    #[test]
+    fn files_without_s_are_smoothed_below_the_crease_angle() {
+        let obj_data = folded_quad("normals-no-s", 0.3, "");
+        for (first, second) in shared_edge_normals(&obj_data) {
+            assert_eq!(position_key(first), position_key(second));
+        }
+        // the averaged normal sits between the two face normals
+        let face_normals = compute_face_normals(&obj_data);
+        let (normal, _) = shared_edge_normals(&obj_data)[0];
+        assert!(normal.dot(face_normals[0]) < 1.0 - 1e-4 && normal.dot(face_normals[1]) < 1.0 - 1e-4);
+    }
+
+    #[test]
+    fn s_off_and_sharp_edges_stay_flat() {
+        for obj_data in [folded_quad("normals-s-off", 0.3, "s off\n"), folded_quad("normals-sharp", 5.0, "")] {
+            let face_normals = compute_face_normals(&obj_data);
+            for (first, second) in shared_edge_normals(&obj_data) {
+                assert_eq!(position_key(first), position_key(face_normals[0]));
+                assert_eq!(position_key(second), position_key(face_normals[1]));
+            }
+        }
+    }
+
+    fn parse(name: &str, contents: &str) -> ObjData {
+        parse_obj_file(&write_test_file(name, contents.as_bytes()), &STRICT).unwrap().0
+    }
+
+    #[test]
+    fn welding_merges_close_vertices() {
+        // 4 and 5 are copies of 2 and 3 moved by less than epsilon, 5 falls in the next grid cell
+        let contents = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1.0001 0 0\nv 0 0.9999 0\nv 1 1 0\nf 1 2 3\nf 4 6 5\nl 4 5\np 6\n";
+        let mut obj_data = parse("weld-close", contents);
+        assert_eq!(weld_vertices(&mut obj_data, 0.001), 2);
+        assert_eq!(obj_data.vertices.len(), 4);
+        let indices: Vec<GLuint> = (0..obj_data.num_indices).map(|i| obj_data.indices.get(i)).collect();
+        assert_eq!(indices, [0, 1, 2, 1, 3, 2]);
+        assert_eq!((obj_data.line_indices.as_slice(), obj_data.point_indices.as_slice()), ([1, 2].as_slice(), [3].as_slice()));
+        // the first vertex is the one kept
+        assert_eq!(obj_data.vertices[1].position.x, 1.0);
+        // nothing left to merge
+        assert_eq!(weld_vertices(&mut obj_data, 0.001), 0);
+    }
+
+    #[test]
+    fn welding_keeps_seams_and_hard_edges() {
+        let positions = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+        let seam = format!("{}vt 0 0\nvt 0.5 0\nf 1/1 2/1 3/1\nf 1/2 3/2 2/2\n", positions);
+        let hard_edge = format!("{}vn 0 0 1\nvn 0 0 -1\nf 1//1 2//1 3//1\nf 1//2 3//2 2//2\n", positions);
+        let colors = "v 0 0 0 1 0 0\nv 0 0 0 0 1 0\nv 1 0 0 1 0 0\nv 0 1 0 1 0 0\nf 1 3 4\nf 2 4 3\n";
+        for (name, contents) in [("weld-seam", seam.as_str()), ("weld-hard-edge", hard_edge.as_str()), ("weld-colors", colors)] {
+            // the copies of a position differ by their texture coordinate, normal or colour
+            let mut obj_data = parse(name, contents);
+            assert_eq!(weld_vertices(&mut obj_data, 0.01), 0, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use gl::types::GLuint;
+
+use crate::models::obj_data::ObjData;
+use crate::models::parse_options::Strictness;
+use crate::models::vec3::Vec3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TriangleIssue {
+    IndexOutOfRange,
+    DegenerateTriangle,
+    NonFiniteValue,
+}
+
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "Error: {}", self.message),
+            Severity::Warning => write!(f, "Warning: {}", self.message),
+        }
+    }
+}
+
+// checks the mesh before it is sent to the gpu.
+// in lenient mode, triangles with out of range indices, repeated indices, a zero area or a non finite
+// position are removed, as are line segments and points with an out of range index or a non finite
+// position, and non finite normals or texture coordinates are reset.
+// an empty mesh is always an error since there is nothing left to display.
+pub fn validate_mesh(obj_data: &mut ObjData, strictness: Strictness) -> Vec<ValidationIssue> {
+    let severity = match strictness {
+        Strictness::Strict => Severity::Error,
+        Strictness::Lenient => Severity::Warning,
+    };
+    let mut issues = Vec::new();
+
+    let num_triangles = obj_data.num_indices / 3;
+    let count = |kind: TriangleIssue| -> usize {
+        (0..num_triangles).filter(|&triangle| triangle_has_issue(obj_data, triangle, kind)).count()
+    };
+    let out_of_range = count(TriangleIssue::IndexOutOfRange);
+    let non_finite = count(TriangleIssue::NonFiniteValue);
+    let degenerate = count(TriangleIssue::DegenerateTriangle);
+
+    if out_of_range > 0 {
+        issues.push(ValidationIssue {
+            severity,
+            message: format!("{} triangle(s) reference a vertex past the end of the {} vertices", out_of_range, obj_data.num_vertices),
+        });
+    }
+    if non_finite > 0 {
+        issues.push(ValidationIssue {
+            severity,
+            message: format!("{} triangle(s) use a vertex with a NaN or infinite position", non_finite),
+        });
+    }
+    if degenerate > 0 {
+        issues.push(ValidationIssue {
+            severity,
+            message: format!("{} degenerate triangle(s) (repeated index or zero area)", degenerate),
+        });
+    }
+    let broken_elements = obj_data.line_indices.chunks(2).chain(obj_data.point_indices.chunks(1))
+        .filter(|element| !is_valid_element(obj_data, element))
+        .count();
+    if broken_elements > 0 {
+        issues.push(ValidationIssue {
+            severity,
+            message: format!("{} line segment(s) or point(s) use a missing vertex or one with a NaN or infinite position", broken_elements),
+        });
+    }
+    let broken_attributes = obj_data.vertices.iter()
+        .filter(|vertex| !is_finite(vertex.normal) || !vertex.text_x.is_finite() || !vertex.text_y.is_finite() || !is_finite(vertex.rgb))
+        .count();
+    if broken_attributes > 0 {
+        issues.push(ValidationIssue {
+            severity,
+            message: format!("{} vertex(es) have a NaN or infinite normal, color or texture coordinate", broken_attributes),
+        });
+    }
+
+    if strictness == Strictness::Lenient {
+        if broken_elements > 0 {
+            let keep_valid = |indices: &Vec<GLuint>, size: usize| -> Vec<GLuint> {
+                indices.chunks(size).filter(|element| is_valid_element(obj_data, element)).flatten().copied().collect()
+            };
+            let line_indices = keep_valid(&obj_data.line_indices, 2);
+            let point_indices = keep_valid(&obj_data.point_indices, 1);
+            obj_data.set_lines_and_points(line_indices, point_indices);
+        }
+        let keep: Vec<bool> = (0..num_triangles)
+            .map(|triangle| {
+                [TriangleIssue::IndexOutOfRange, TriangleIssue::NonFiniteValue, TriangleIssue::DegenerateTriangle]
+                    .iter()
+                    .all(|&kind| !triangle_has_issue(obj_data, triangle, kind))
+            })
+            .collect();
+        // broken attributes are reset in place, the triangles using them are kept
+        for vertex in obj_data.vertices.iter_mut() {
+            if !is_finite(vertex.normal) {
+                // a zero normal gets generated again afterwards
+                vertex.normal = Vec3::new(0.0, 0.0, 0.0);
+            }
+            if !is_finite(vertex.rgb) {
+                vertex.rgb = Vec3::new(0.5, 0.5, 0.5);
+            }
+            if !vertex.text_x.is_finite() || !vertex.text_y.is_finite() {
+                vertex.text_x = 0.0;
+                vertex.text_y = 0.0;
+            }
+        }
+        if keep.iter().any(|&kept| !kept) {
+            remove_triangles(obj_data, &keep);
+        } else if broken_attributes > 0 {
+            obj_data.refresh_vertices_raw();
+        }
+    }
+
+    if obj_data.num_indices == 0 && obj_data.line_indices.is_empty() && obj_data.point_indices.is_empty() {
+        issues.push(ValidationIssue {
+            severity: Severity::Error,
+            message: "the mesh has no triangle, line or point to display".to_string(),
+        });
+    }
+    issues
+}
+
+fn triangle_has_issue(obj_data: &ObjData, triangle: usize, kind: TriangleIssue) -> bool {
+    let corners = [
+        obj_data.indices.get(triangle * 3),
+        obj_data.indices.get(triangle * 3 + 1),
+        obj_data.indices.get(triangle * 3 + 2),
+    ];
+    let in_range = corners.iter().all(|&index| (index as usize) < obj_data.vertices.len());
+    match kind {
+        TriangleIssue::IndexOutOfRange => !in_range,
+        TriangleIssue::NonFiniteValue => in_range && corners.iter().any(|&index| !is_finite(obj_data.vertices[index as usize].position)),
+        TriangleIssue::DegenerateTriangle => {
+            if !in_range {
+                return false;
+            }
+            if corners[0] == corners[1] || corners[1] == corners[2] || corners[0] == corners[2] {
+                return true;
+            }
+            let a = obj_data.vertices[corners[0] as usize].position;
+            let b = obj_data.vertices[corners[1] as usize].position;
+            let c = obj_data.vertices[corners[2] as usize].position;
+            b.sub(a).cross(c.sub(a)).length() == 0.0
+        }
+    }
+}
+
+// a line segment or a point is kept only if all its vertices exist and have a finite position
+fn is_valid_element(obj_data: &ObjData, indices: &[GLuint]) -> bool {
+    indices.iter().all(|&index| obj_data.vertices.get(index as usize).is_some_and(|vertex| is_finite(vertex.position)))
+}
+
+fn is_finite(v: Vec3) -> bool {
+    v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
+}
+
+// drops the triangles not kept and the vertices nobody uses anymore, keeping the material ranges,
+// sub-meshes and smoothing groups in step with the new index buffer
+fn remove_triangles(obj_data: &mut ObjData, keep: &[bool]) {
+    // kept_before[t] is the number of kept triangles before triangle t
+    let mut kept_before = Vec::with_capacity(keep.len() + 1);
+    kept_before.push(0);
+    for &kept in keep {
+        kept_before.push(kept_before.last().unwrap() + kept as usize);
+    }
+    let new_index = |old_index: usize| kept_before[old_index / 3] * 3;
+
+    let mut remap: Vec<Option<GLuint>> = vec![None; obj_data.vertices.len()];
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut smoothing_groups = Vec::new();
+    for (triangle, _) in keep.iter().enumerate().filter(|(_, &kept)| kept) {
+        for corner in 0..3 {
+            let old = obj_data.indices.get(triangle * 3 + corner) as usize;
+            let index = match remap[old] {
+                Some(index) => index,
+                None => {
+                    let index = vertices.len() as GLuint;
+                    vertices.push(obj_data.vertices[old].clone());
+                    remap[old] = Some(index);
+                    index
+                }
+            };
+            indices.push(index);
+        }
+        smoothing_groups.push(obj_data.smoothing_groups[triangle]);
+    }
+    obj_data.remap_lines_and_points(&mut remap, &mut vertices);
+
+    for range in obj_data.material_ranges.iter_mut() {
+        let end = new_index(range.first_index + range.num_indices);
+        range.first_index = new_index(range.first_index);
+        range.num_indices = end - range.first_index;
+    }
+    obj_data.material_ranges.retain(|range| range.num_indices > 0);
+    for sub_mesh in obj_data.sub_meshes.iter_mut() {
+        let end = new_index(sub_mesh.first_index + sub_mesh.num_indices);
+        sub_mesh.first_index = new_index(sub_mesh.first_index);
+        sub_mesh.num_indices = end - sub_mesh.first_index;
+    }
+    obj_data.sub_meshes.retain(|sub_mesh| sub_mesh.num_indices > 0);
+    obj_data.smoothing_groups = smoothing_groups;
+    obj_data.set_geometry(vertices, indices);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj_parser::parse_obj_file;
+    use crate::test_support::{write_test_file, LENIENT};
+
+    fn parse(name: &str, contents: &str) -> ObjData {
+        parse_obj_file(&write_test_file(name, contents.as_bytes()), &LENIENT).unwrap().0
+    }
+
+    #[test]
+    fn removed_triangles_keep_the_ranges_in_step() {
+        write_test_file("validation-ranges.mtl", b"newmtl a\nnewmtl b\n");
+        // the degenerate faces repeat a corner, the last one uses a NaN position
+        let contents = "mtllib validation-ranges.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nv nan 0 0\n\
+            o first\nusemtl a\nf 1 2 3\nf 1 1 2\n\
+            o second\nusemtl b\nf 1 2 2\n\
+            o third\nf 2 3 1\nusemtl a\nf 1 2 4\nf 3 1 2\n";
+        let mut obj_data = parse("validation-ranges", contents);
+        let issues = validate_mesh(&mut obj_data, Strictness::Lenient);
+        assert_eq!(issues.len(), 2);
+        assert!(issues.iter().all(|issue| issue.severity == Severity::Warning));
+
+        assert_eq!((obj_data.num_indices, obj_data.smoothing_groups.len()), (9, 3));
+        let sub_meshes: Vec<_> = obj_data.sub_meshes.iter()
+            .map(|sub_mesh| (sub_mesh.name(), sub_mesh.first_index, sub_mesh.num_indices))
+            .collect();
+        assert_eq!(sub_meshes, [("first".to_string(), 0, 3), ("third".to_string(), 3, 6)]);
+        let ranges: Vec<_> = obj_data.material_ranges.iter()
+            .map(|range| (range.material, range.first_index, range.num_indices))
+            .collect();
+        // the material b range lost its only triangle, the two ranges of a around it stay apart
+        assert_eq!(ranges, [(Some(0), 0, 3), (Some(1), 3, 3), (Some(0), 6, 3)]);
+        // the NaN vertex isn't used anymore
+        assert_eq!(obj_data.vertices.len(), 3);
+    }
+
+    #[test]
+    fn broken_attributes_are_reset_without_touching_the_triangles() {
+        let mut obj_data = parse("validation-attributes", "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt inf 0\nvn nan 0 1\nf 1/1/1 2/1/1 3/1/1\n");
+        let indices: Vec<GLuint> = (0..obj_data.indices.len()).map(|i| obj_data.indices.get(i)).collect();
+        let issues = validate_mesh(&mut obj_data, Strictness::Lenient);
+        assert_eq!(issues.len(), 1);
+        assert_eq!((0..obj_data.indices.len()).map(|i| obj_data.indices.get(i)).collect::<Vec<_>>(), indices);
+        for vertex in &obj_data.vertices {
+            assert_eq!((vertex.normal.length(), vertex.text_x, vertex.text_y), (0.0, 0.0, 0.0));
+        }
+        assert!(obj_data.vertices_raw.iter().all(|value| value.is_finite()));
+    }
+
+    #[test]
+    fn strict_mode_reports_without_repairing() {
+        let mut obj_data = parse("validation-strict", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nf 1 1 2\n");
+        let issues = validate_mesh(&mut obj_data, Strictness::Strict);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert_eq!(obj_data.num_indices, 6);
+    }
+}
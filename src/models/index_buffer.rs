@@ -0,0 +1,80 @@
+use gl::types::{GLenum, GLuint, GLushort};
+use std::mem::size_of;
+use std::os::raw::c_void;
+
+// element buffer stored with the narrowest type that can address every vertex
+#[derive(Debug, Clone)]
+pub enum IndexBuffer {
+    U16(Vec<GLushort>),
+    U32(Vec<GLuint>),
+}
+
+impl IndexBuffer {
+    pub fn new(indices: Vec<GLuint>) -> Self {
+        if indices.iter().all(|&index| index <= GLushort::MAX as GLuint) {
+            IndexBuffer::U16(indices.into_iter().map(|index| index as GLushort).collect())
+        } else {
+            IndexBuffer::U32(indices)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            IndexBuffer::U16(indices) => indices.len(),
+            IndexBuffer::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn get(&self, i: usize) -> GLuint {
+        match self {
+            IndexBuffer::U16(indices) => indices[i] as GLuint,
+            IndexBuffer::U32(indices) => indices[i],
+        }
+    }
+
+    pub fn element_size(&self) -> usize {
+        match self {
+            IndexBuffer::U16(_) => size_of::<GLushort>(),
+            IndexBuffer::U32(_) => size_of::<GLuint>(),
+        }
+    }
+
+    pub fn gl_type(&self) -> GLenum {
+        match self {
+            IndexBuffer::U16(_) => gl::UNSIGNED_SHORT,
+            IndexBuffer::U32(_) => gl::UNSIGNED_INT,
+        }
+    }
+
+    pub fn as_ptr(&self) -> *const c_void {
+        match self {
+            IndexBuffer::U16(indices) => indices.as_ptr() as *const c_void,
+            IndexBuffer::U32(indices) => indices.as_ptr() as *const c_void,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn narrowest_type_that_fits() {
+        let narrow = IndexBuffer::new(vec![0, 1, 65535]);
+        assert!(matches!(narrow, IndexBuffer::U16(_)));
+        assert_eq!((narrow.element_size(), narrow.gl_type()), (2, gl::UNSIGNED_SHORT));
+
+        let wide = IndexBuffer::new(vec![0, 1, 65536]);
+        assert!(matches!(wide, IndexBuffer::U32(_)));
+        assert_eq!((wide.element_size(), wide.gl_type()), (4, gl::UNSIGNED_INT));
+    }
+
+    #[test]
+    fn indices_read_back_unchanged() {
+        for indices in [vec![7, 65535, 0], vec![7, 65536, u32::MAX]] {
+            let buffer = IndexBuffer::new(indices.clone());
+            assert_eq!(buffer.len(), 3);
+            assert_eq!((0..buffer.len()).map(|i| buffer.get(i)).collect::<Vec<_>>(), indices);
+        }
+    }
+}
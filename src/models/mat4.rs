@@ -31,7 +31,7 @@ impl Mat4 {
     }
 
     pub fn translate(&self, x: f32, y: f32, z: f32) -> Mat4 {
-        let mut result = self.clone();
+        let mut result = *self;
         result.0[3][0] += x;
         result.0[3][1] += y;
         result.0[3][2] += z;
@@ -42,7 +42,7 @@ impl Mat4 {
         let cos_angle = angle.cos();
         let sin_angle = angle.sin();
 
-        let mut result = self.clone();
+        let mut result = *self;
         result *= Self([
             [1.0, 0.0, 0.0, 0.0],
             [0.0, cos_angle, -sin_angle, 0.0],
@@ -56,7 +56,7 @@ impl Mat4 {
         let cos_angle = angle.cos();
         let sin_angle = angle.sin();
 
-        let mut result = self.clone();
+        let mut result = *self;
         result *= Self([
             [cos_angle, 0.0, sin_angle, 0.0],
             [0.0, 1.0, 0.0, 0.0],
@@ -70,7 +70,7 @@ impl Mat4 {
         let cos_angle = angle.cos();
         let sin_angle = angle.sin();
 
-        let mut result = self.clone();
+        let mut result = *self;
         result *= Self([
             [cos_angle, -sin_angle, 0.0, 0.0],
             [sin_angle, cos_angle, 0.0, 0.0],
@@ -0,0 +1,60 @@
+use gl::types::GLuint;
+
+use crate::models::vec3::Vec3;
+
+pub struct Material {
+    pub name: String,
+    pub ambient: Vec3,
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+    pub emissive: Vec3,
+    pub shininess: f32,
+    pub dissolve: f32,
+    pub optical_density: f32,
+    pub illum: u32,
+    pub ambient_map: Option<String>,
+    pub diffuse_map: Option<String>,
+    pub specular_map: Option<String>,
+    pub dissolve_map: Option<String>,
+    pub bump_map: Option<String>,
+    // image stored inside the model file (gltf), used instead of diffuse_map
+    pub diffuse_image: Option<Vec<u8>>,
+    pub diffuse_texture_id: Option<GLuint>,
+}
+
+impl Material {
+    pub fn new(name: &str) -> Self {
+        Material {
+            name: name.to_string(),
+            ambient: Vec3::new(0.0, 0.0, 0.0),
+            diffuse: Vec3::new(0.8, 0.8, 0.8),
+            specular: Vec3::new(0.0, 0.0, 0.0),
+            emissive: Vec3::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            dissolve: 1.0,
+            optical_density: 1.0,
+            illum: 2,
+            ambient_map: None,
+            diffuse_map: None,
+            specular_map: None,
+            dissolve_map: None,
+            bump_map: None,
+            diffuse_image: None,
+            diffuse_texture_id: None,
+        }
+    }
+}
+
+impl std::fmt::Display for Material {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: Ka {}, Kd {}, Ks {}, Ns {}, d {}", self.name, self.ambient, self.diffuse, self.specular, self.shininess, self.dissolve)
+    }
+}
+
+// a run of consecutive indices drawn with the same material (None means the default look)
+#[derive(Debug, Clone, Copy)]
+pub struct MaterialRange {
+    pub material: Option<usize>,
+    pub first_index: usize,
+    pub num_indices: usize,
+}
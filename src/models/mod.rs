@@ -1,4 +1,9 @@
 pub mod vec3;
 pub mod obj_data;
 pub mod gl_var;
-pub mod mat4;
\ No newline at end of file
+pub mod mat4;
+pub mod index_buffer;
+pub mod material;
+pub mod sub_mesh;
+pub mod obj_parse_error;
+pub mod parse_options;
\ No newline at end of file
@@ -1,5 +1,10 @@
-use gl::types::GLushort;
+use gl::types::GLuint;
+use std::mem::size_of;
 
+use crate::globals::{LINE_WIDTH, POINT_SIZE};
+use crate::models::index_buffer::IndexBuffer;
+use crate::models::material::{Material, MaterialRange};
+use crate::models::sub_mesh::SubMesh;
 use crate::models::vec3::Vec3;
 
 pub struct ObjData {
@@ -7,9 +12,19 @@ pub struct ObjData {
     pub num_vertices: u32,
     pub vertices_raw: Vec<f32>,
     pub vertex_buffer_size: usize,
-    pub indices: Vec<GLushort>,
+    // the triangles come first in the index buffer, followed by the line segments then the points
+    pub indices: IndexBuffer,
     pub num_indices: usize,
     pub indices_buffer_size: usize,
+    pub line_indices: Vec<GLuint>,
+    pub point_indices: Vec<GLuint>,
+    pub smoothing_groups: Vec<u32>,
+    pub materials: Vec<Material>,
+    // the other files the mesh was read from (.mtl libraries, gltf buffers), whether they could be read or not
+    pub dependencies: Vec<String>,
+    pub material_ranges: Vec<MaterialRange>,
+    pub sub_meshes: Vec<SubMesh>,
+    pub selected_sub_mesh: Option<usize>,
     pub center_x: f32,
     pub center_y: f32,
     pub center_z: f32,
@@ -20,10 +35,99 @@ pub struct ObjData {
     pub position_x: f32,
     pub position_y: f32,
     pub position_z: f32,
-    pub scale_x: f32,
-    pub scale_y: f32,
-    pub scale_z: f32,
     pub display_texture: bool,
+    // the file gave its own vertex colours, they are blended across the faces instead of flat shaded
+    pub has_vertex_colors: bool,
+    // names of the extra per-vertex values some formats carry (confidence, intensity...), they can be shown as colours
+    pub vertex_properties: Vec<String>,
+    pub line_width: f32,
+    pub point_size: f32,
+}
+
+impl ObjData {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<GLuint>, smoothing_groups: Vec<u32>) -> Self {
+        let mut obj_data = ObjData {
+            vertices: Vec::new(),
+            num_vertices: 0,
+            vertices_raw: Vec::new(),
+            vertex_buffer_size: 0,
+            indices: IndexBuffer::U16(Vec::new()),
+            num_indices: 0,
+            indices_buffer_size: 0,
+            line_indices: Vec::new(),
+            point_indices: Vec::new(),
+            smoothing_groups,
+            materials: Vec::new(),
+            dependencies: Vec::new(),
+            material_ranges: Vec::new(),
+            sub_meshes: Vec::new(),
+            selected_sub_mesh: None,
+            center_x: 0.0,
+            center_y: 0.0,
+            center_z: 0.0,
+            longest_distance: 0.0,
+            orientation_x: 0.0,
+            orientation_y: 0.0,
+            orientation_z: 0.0,
+            position_x: 0.0,
+            position_y: 0.0,
+            position_z: 0.0,
+            display_texture: false,
+            has_vertex_colors: false,
+            vertex_properties: Vec::new(),
+            line_width: LINE_WIDTH,
+            point_size: POINT_SIZE,
+        };
+        obj_data.set_geometry(vertices, indices);
+        obj_data
+    }
+
+    // replaces the mesh and recomputes everything derived from it (raw buffers, sizes, bounding box)
+    pub fn set_geometry(&mut self, vertices: Vec<Vertex>, indices: Vec<GLuint>) {
+        self.vertices_raw = get_vertices_array(&vertices);
+        self.vertex_buffer_size = self.vertices_raw.len() * size_of::<f32>();
+        self.num_vertices = vertices.len() as u32;
+        let (center_x, center_y, center_z, longest_distance) = get_center_and_size(&vertices);
+        self.center_x = center_x;
+        self.center_y = center_y;
+        self.center_z = center_z;
+        self.longest_distance = longest_distance;
+        self.vertices = vertices;
+        self.num_indices = indices.len();
+        let mut all_indices = indices;
+        all_indices.extend(&self.line_indices);
+        all_indices.extend(&self.point_indices);
+        self.indices = IndexBuffer::new(all_indices);
+        self.indices_buffer_size = self.indices.len() * self.indices.element_size();
+    }
+
+    // rebuilds the buffer sent to the gpu after vertex attributes were changed in place
+    pub fn refresh_vertices_raw(&mut self) {
+        self.vertices_raw = get_vertices_array(&self.vertices);
+    }
+
+    pub fn set_lines_and_points(&mut self, line_indices: Vec<GLuint>, point_indices: Vec<GLuint>) {
+        self.line_indices = line_indices;
+        self.point_indices = point_indices;
+        let triangles = (0..self.num_indices).map(|i| self.indices.get(i)).collect();
+        let vertices = std::mem::take(&mut self.vertices);
+        self.set_geometry(vertices, triangles);
+    }
+
+    // to be called before set_geometry when the vertices are rebuilt from the triangles.
+    // remap gives the new index of each old vertex, vertices only used by lines or points
+    // don't have one yet and are appended to the new vertices
+    pub fn remap_lines_and_points(&mut self, remap: &mut [Option<GLuint>], vertices: &mut Vec<Vertex>) {
+        let old_vertices = &self.vertices;
+        let mut map = |old: &mut GLuint| {
+            *old = *remap[*old as usize].get_or_insert_with(|| {
+                vertices.push(old_vertices[*old as usize].clone());
+                (vertices.len() - 1) as GLuint
+            });
+        };
+        self.line_indices.iter_mut().for_each(&mut map);
+        self.point_indices.iter_mut().for_each(&mut map);
+    }
 }
 
 impl std::fmt::Display for ObjData {
@@ -36,11 +140,15 @@ impl std::fmt::Display for ObjData {
     }
 }
 
+#[derive(Clone)]
 pub struct Vertex {
     pub position: Vec3,
     pub rgb: Vec3,
     pub text_x: f32,
     pub text_y: f32,
+    pub normal: Vec3,
+    // values of the extra scalar properties of the file, in the order of ObjData::vertex_properties
+    pub properties: Vec<f32>,
 }
 
 impl std::fmt::Display for Vertex {
@@ -49,11 +157,109 @@ impl std::fmt::Display for Vertex {
         write!(f, "textx: {}", self.text_x)?;
         write!(f, "texty: {}", self.text_y)?;
         write!(f, ", RGB: {}", self.rgb)?;
+        write!(f, ", Normal: {}", self.normal)?;
 
         Ok(())
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FaceIndex {
+    pub vertex: GLuint,
+    pub texture: Option<GLuint>,
+    pub normal: Option<GLuint>,
+}
+
+// state set by the statements preceding a face ("s", "usemtl")
+#[derive(Debug, Clone, Copy)]
+pub struct FaceAttributes {
+    pub smoothing_group: u32,
+    pub material: Option<usize>,
+}
+
 pub struct Face {
-    pub indices: Vec<GLushort>,
-}
\ No newline at end of file
+    pub indices: Vec<FaceIndex>,
+    pub attributes: FaceAttributes,
+    pub line_number: usize,
+}
+
+fn get_vertices_array(vertices: &Vec<Vertex>) -> Vec<f32> {
+    let mut vertices_raw = Vec::with_capacity(vertices.len() * 11);
+
+    for vertex in vertices {
+        vertices_raw.push(vertex.position.x);
+        vertices_raw.push(vertex.position.y);
+        vertices_raw.push(vertex.position.z);
+        vertices_raw.push(vertex.rgb.x);
+        vertices_raw.push(vertex.rgb.y);
+        vertices_raw.push(vertex.rgb.z);
+        vertices_raw.push(vertex.text_x);
+        vertices_raw.push(vertex.text_y);
+        vertices_raw.push(vertex.normal.x);
+        vertices_raw.push(vertex.normal.y);
+        vertices_raw.push(vertex.normal.z);
+    }
+
+    vertices_raw
+}
+
+fn get_center_and_size(vertices: &Vec<Vertex>) -> (f32, f32, f32, f32) {
+    let mut min_x = f32::MAX;
+    let mut max_x = f32::MIN;
+    let mut min_y = f32::MAX;
+    let mut max_y = f32::MIN;
+    let mut min_z = f32::MAX;
+    let mut max_z = f32::MIN;
+    let mut longest = f32::MIN;
+
+    for vertex in vertices {
+        if vertex.position.x < min_x { min_x = vertex.position.x; }
+        if vertex.position.x > max_x { max_x = vertex.position.x; }
+        if vertex.position.y < min_y { min_y = vertex.position.y; }
+        if vertex.position.y > max_y { max_y = vertex.position.y; }
+        if vertex.position.z < min_z { min_z = vertex.position.z; }
+        if vertex.position.z > max_z { max_z = vertex.position.z; }
+    }
+    let center_x = (min_x + max_x) / 2.0;
+    let center_y = (min_y + max_y) / 2.0;
+    let center_z = (min_z + max_z) / 2.0;
+    let obj_max_width = max_x - min_x;
+    let obj_max_height = max_y - min_y;
+    let obj_max_depth = max_z - min_z;
+
+    if obj_max_width > longest {longest = obj_max_width;}
+    if obj_max_height > longest {longest = obj_max_height;}
+    if obj_max_depth > longest {longest = obj_max_depth;}
+
+    (center_x, center_y, center_z, longest)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(x: f32) -> Vertex {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        Vertex {position: Vec3::new(x, 0.0, 0.0), rgb: zero, text_x: 0.0, text_y: 0.0, normal: zero, properties: Vec::new()}
+    }
+
+    #[test]
+    fn lines_and_points_follow_the_remapped_vertices() {
+        let mut obj_data = ObjData::new((0..5).map(|x| vertex(x as f32)).collect(), vec![0, 1, 2], vec![1]);
+        obj_data.set_lines_and_points(vec![2, 3, 3, 0], vec![4, 3]);
+        assert_eq!(obj_data.indices.len(), 3 + 4 + 2);
+
+        // the triangle vertices were rebuilt in reverse, 3 and 4 are only used by the line and the points
+        let mut remap = vec![Some(2), Some(1), Some(0), None, None];
+        let mut vertices = vec![vertex(2.0), vertex(1.0), vertex(0.0)];
+        obj_data.remap_lines_and_points(&mut remap, &mut vertices);
+        assert_eq!(obj_data.line_indices, [0, 3, 3, 2]);
+        assert_eq!(obj_data.point_indices, [4, 3]);
+        let positions: Vec<f32> = vertices.iter().map(|vertex| vertex.position.x).collect();
+        assert_eq!(positions, [2.0, 1.0, 0.0, 3.0, 4.0]);
+
+        obj_data.set_geometry(vertices, vec![2, 1, 0]);
+        // lines and points are stored after the triangles in the index buffer
+        let indices: Vec<GLuint> = (0..obj_data.indices.len()).map(|i| obj_data.indices.get(i)).collect();
+        assert_eq!(indices, [2, 1, 0, 0, 3, 3, 2, 4, 3]);
+    }
+}
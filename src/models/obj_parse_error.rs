@@ -0,0 +1,148 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjParseErrorKind {
+    Io(String),
+    InvalidNumber,
+    InvalidIndex,
+    ZeroIndex,
+    RelativeIndexOutOfRange,
+    IndexOutOfRange(&'static str),
+    MissingValue(&'static str),
+    UnexpectedValue(&'static str),
+    InvalidFaceVertex,
+    NotEnoughFaceVertices,
+    NotEnoughLineVertices,
+    InvalidSmoothingGroup,
+    UnknownStatement,
+    UnsupportedWeight,
+    UnsupportedCurveType,
+    InvalidFreeForm(&'static str),
+    Malformed(&'static str),
+    Unsupported(String),
+    MaterialLibrary(String),
+    UnknownMaterial,
+}
+
+impl std::fmt::Display for ObjParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjParseErrorKind::Io(e) => write!(f, "could not read file: {}", e),
+            ObjParseErrorKind::InvalidNumber => write!(f, "invalid number"),
+            ObjParseErrorKind::InvalidIndex => write!(f, "invalid index"),
+            ObjParseErrorKind::ZeroIndex => write!(f, "indices start at 1, 0 is not a valid index"),
+            ObjParseErrorKind::RelativeIndexOutOfRange => write!(f, "relative index points before the first element"),
+            ObjParseErrorKind::IndexOutOfRange(element) => write!(f, "index refers to a {} that doesn't exist", element),
+            ObjParseErrorKind::MissingValue(expected) => write!(f, "missing {}", expected),
+            ObjParseErrorKind::UnexpectedValue(reason) => write!(f, "unexpected value, {}", reason),
+            ObjParseErrorKind::InvalidFaceVertex => write!(f, "invalid face vertex, expected v, v/vt, v//vn or v/vt/vn"),
+            ObjParseErrorKind::NotEnoughFaceVertices => write!(f, "a face must have at least 3 vertices"),
+            ObjParseErrorKind::NotEnoughLineVertices => write!(f, "a line must have at least 2 vertices"),
+            ObjParseErrorKind::InvalidSmoothingGroup => write!(f, "invalid smoothing group, expected a number or 'off'"),
+            ObjParseErrorKind::UnknownStatement => write!(f, "unknown or unsupported statement"),
+            ObjParseErrorKind::UnsupportedWeight => write!(f, "vertex weight (w) is only supported on the control points of rational curves and surfaces"),
+            ObjParseErrorKind::UnsupportedCurveType => write!(f, "unsupported curve type, expected bezier or bspline"),
+            ObjParseErrorKind::InvalidFreeForm(reason) => write!(f, "invalid free-form geometry, {}", reason),
+            ObjParseErrorKind::Malformed(reason) => write!(f, "malformed file, {}", reason),
+            ObjParseErrorKind::Unsupported(feature) => write!(f, "unsupported feature: {}", feature),
+            ObjParseErrorKind::MaterialLibrary(e) => write!(f, "could not load material library: {}", e),
+            ObjParseErrorKind::UnknownMaterial => write!(f, "unknown material"),
+        }
+    }
+}
+
+// line and column start from 1, 0 means the error isn't tied to a line or a column
+#[derive(Debug, Clone)]
+pub struct ObjParseError {
+    pub file_path: String,
+    pub line_number: usize,
+    pub column: usize,
+    pub token: String,
+    pub kind: ObjParseErrorKind,
+}
+
+impl ObjParseError {
+    pub fn new(file_path: &str, line_number: usize, column: usize, token: &str, kind: ObjParseErrorKind) -> Self {
+        ObjParseError {
+            file_path: file_path.to_string(),
+            line_number,
+            column,
+            token: token.to_string(),
+            kind,
+        }
+    }
+
+    pub fn io(file_path: &str, error: std::io::Error) -> Self {
+        ObjParseError::new(file_path, 0, 0, "", ObjParseErrorKind::Io(error.to_string()))
+    }
+
+    // compiler-style report quoting the offending line, with the token underlined when its column is known
+    pub fn diagnostic(&self) -> String {
+        let mut report = format!("error: {}\n", self.kind);
+        report.push_str(&format!(" --> {}\n", self.location()));
+        if self.line_number == 0 {
+            return report;
+        }
+
+        let source_line = match self.read_source_line() {
+            Some(line) => line,
+            None => return report,
+        };
+        let gutter = " ".repeat(self.line_number.to_string().len());
+        report.push_str(&format!("{} |\n", gutter));
+        report.push_str(&format!("{} | {}\n", self.line_number, source_line));
+        if self.column > 0 {
+            let underline = "^".repeat(self.token.chars().count().max(1));
+            report.push_str(&format!("{} | {}{}\n", gutter, " ".repeat(self.column - 1), underline));
+        }
+        report
+    }
+
+    // path:line:column, leaving out what isn't known
+    fn location(&self) -> String {
+        match (self.line_number, self.column) {
+            (0, _) => self.file_path.clone(),
+            (line, 0) => format!("{}:{}", self.file_path, line),
+            (line, column) => format!("{}:{}:{}", self.file_path, line, column),
+        }
+    }
+
+    fn read_source_line(&self) -> Option<String> {
+        let file = File::open(&self.file_path).ok()?;
+        let line = BufReader::new(file).lines().nth(self.line_number - 1)?.ok()?;
+        // tabs would shift the underline
+        Some(line.replace('\t', " "))
+    }
+}
+
+impl std::fmt::Display for ObjParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location(), self.kind)
+    }
+}
+
+impl std::error::Error for ObjParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_test_file;
+
+    #[test]
+    fn diagnostic_underlines_the_token() {
+        let path = write_test_file("diagnostic.obj", b"v 0 0 0\n\tf 1 2 abc\n");
+        let error = ObjParseError::new(&path, 2, 8, "abc", ObjParseErrorKind::InvalidIndex);
+        assert_eq!(error.to_string(), format!("{}:2:8: invalid index", path));
+        let expected = format!("error: invalid index\n --> {}:2:8\n  |\n2 |  f 1 2 abc\n  |        ^^^\n", path);
+        assert_eq!(error.diagnostic(), expected);
+    }
+
+    #[test]
+    fn unknown_locations_are_left_out() {
+        let error = ObjParseError::new("mesh.obj", 3, 0, "", ObjParseErrorKind::NotEnoughFaceVertices);
+        assert_eq!(error.to_string(), "mesh.obj:3: a face must have at least 3 vertices");
+        let error = ObjParseError::io("missing.obj", std::io::Error::other("gone"));
+        assert_eq!(error.diagnostic(), "error: could not read file: gone\n --> missing.obj\n");
+    }
+}
@@ -0,0 +1,19 @@
+// strict turns every recoverable problem into an error, lenient works around it and reports a warning
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strictness {
+    Strict,
+    Lenient,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParseOptions {
+    pub strictness: Strictness,
+    // segments per knot span when tessellating free-form curves and surfaces
+    pub resolution: u32,
+}
+
+impl ParseOptions {
+    pub fn is_lenient(&self) -> bool {
+        self.strictness == Strictness::Lenient
+    }
+}
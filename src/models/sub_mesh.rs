@@ -0,0 +1,35 @@
+use crate::models::vec3::Vec3;
+
+// the faces following an "o" or "g" statement, drawn separately so they can be shown, hidden or moved on their own
+pub struct SubMesh {
+    pub object: String,
+    pub group: String,
+    pub first_index: usize,
+    pub num_indices: usize,
+    pub visible: bool,
+    pub highlighted: bool,
+    pub offset: Vec3,
+}
+
+impl SubMesh {
+    pub fn new(object: &str, group: &str, first_index: usize) -> Self {
+        SubMesh {
+            object: object.to_string(),
+            group: group.to_string(),
+            first_index,
+            num_indices: 0,
+            visible: true,
+            highlighted: false,
+            offset: Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match (self.object.is_empty(), self.group.is_empty()) {
+            (true, true) => "default".to_string(),
+            (false, true) => self.object.clone(),
+            (true, false) => self.group.clone(),
+            (false, false) => format!("{}/{}", self.object, self.group),
+        }
+    }
+}
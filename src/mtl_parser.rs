@@ -0,0 +1,111 @@
+use std::fs;
+use std::io::{self, Error};
+use std::path::Path;
+use std::str::SplitWhitespace;
+
+use crate::models::material::Material;
+use crate::models::vec3::Vec3;
+use crate::obj_tokenizer::Statements;
+
+
+pub fn parse_mtl_file(file_path: &str) -> Result<Vec<Material>, Error> {
+    let content = fs::read(file_path)?;
+    let directory = Path::new(file_path).parent().unwrap_or(Path::new(""));
+
+    let mut materials: Vec<Material> = Vec::new();
+
+    for statement in Statements::new(&content) {
+        let mut parts = statement.tokens();
+
+        let keyword = match parts.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            let name = parts.next().ok_or_else(|| Error::new(io::ErrorKind::InvalidData, "A material must have a name"))?;
+            materials.push(Material::new(name));
+            continue;
+        }
+
+        let material = materials.last_mut()
+            .ok_or_else(|| Error::new(io::ErrorKind::InvalidData, format!("'{}' found before any newmtl", keyword)))?;
+        match keyword {
+            "Ka" => material.ambient = parse_color(&mut parts)?,
+            "Kd" => material.diffuse = parse_color(&mut parts)?,
+            "Ks" => material.specular = parse_color(&mut parts)?,
+            "Ke" => material.emissive = parse_color(&mut parts)?,
+            "Ns" => material.shininess = parse_float(&mut parts)?,
+            "d" => material.dissolve = parse_float(&mut parts)?,
+            "Tr" => material.dissolve = 1.0 - parse_float(&mut parts)?,
+            "Ni" => material.optical_density = parse_float(&mut parts)?,
+            "illum" => {
+                let illum = parts.next().ok_or_else(|| Error::new(io::ErrorKind::InvalidData, "Invalid illum format"))?;
+                material.illum = match illum.parse::<u32>() {
+                    Ok(value) => value,
+                    Err(e) => return Err(Error::new(io::ErrorKind::InvalidData, e)),
+                };
+            }
+            "map_Ka" => material.ambient_map = Some(parse_map_path(&mut parts, directory)?),
+            "map_Kd" => material.diffuse_map = Some(parse_map_path(&mut parts, directory)?),
+            "map_Ks" => material.specular_map = Some(parse_map_path(&mut parts, directory)?),
+            "map_d" => material.dissolve_map = Some(parse_map_path(&mut parts, directory)?),
+            "map_Bump" | "map_bump" | "bump" => material.bump_map = Some(parse_map_path(&mut parts, directory)?),
+            _ => {}
+        }
+    }
+    Ok(materials)
+}
+
+// "Kd r" is a valid shorthand for "Kd r r r"
+fn parse_color(parts: &mut SplitWhitespace) -> Result<Vec3, Error> {
+    let r = parse_float(parts)?;
+    let g = match parts.next() {
+        Some(g) => g.parse::<f32>().map_err(|e| Error::new(io::ErrorKind::InvalidData, e))?,
+        None => return Ok(Vec3::new(r, r, r)),
+    };
+    let b = parse_float(parts)?;
+    Ok(Vec3::new(r, g, b))
+}
+
+fn parse_float(parts: &mut SplitWhitespace) -> Result<f32, Error> {
+    let value = parts.next().ok_or_else(|| Error::new(io::ErrorKind::InvalidData, "Missing material value"))?;
+    match value.parse::<f32>() {
+        Ok(value) => Ok(value),
+        Err(e) => Err(Error::new(io::ErrorKind::InvalidData, e)),
+    }
+}
+
+// texture options (-s, -o, -bm...) come before the file name, which is always the last token
+fn parse_map_path(parts: &mut SplitWhitespace, directory: &Path) -> Result<String, Error> {
+    let file_name = parts.last().ok_or_else(|| Error::new(io::ErrorKind::InvalidData, "Missing texture file name"))?;
+    Ok(directory.join(file_name).to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{test_path, write_test_file};
+
+    #[test]
+    fn material_statements() {
+        let contents = "# two materials\nnewmtl red\nKa 0.1 0.2 0.3\nKd 1\nNs 96\nTr 0.25\nillum 1\nmap_Kd -s 2 2 1 textures/red.bmp\n\nnewmtl plain\n";
+        let materials = parse_mtl_file(&write_test_file("mtl-statements.mtl", contents.as_bytes())).unwrap();
+        assert_eq!(materials.len(), 2);
+        let red = &materials[0];
+        assert_eq!(red.name, "red");
+        assert_eq!((red.ambient.x, red.ambient.y, red.ambient.z), (0.1, 0.2, 0.3));
+        assert_eq!((red.diffuse.x, red.diffuse.y, red.diffuse.z), (1.0, 1.0, 1.0));
+        assert_eq!((red.shininess, red.dissolve, red.illum), (96.0, 0.75, 1));
+        // texture paths are relative to the mtl file, the options before them are skipped
+        assert_eq!(red.diffuse_map.as_deref(), Some(test_path("textures/red.bmp").to_str().unwrap()));
+        assert_eq!((materials[1].name.as_str(), materials[1].diffuse.x), ("plain", 0.8));
+    }
+
+    #[test]
+    fn malformed_materials_are_refused() {
+        for contents in ["Kd 1 1 1\n", "newmtl\n", "newmtl a\nKd 1 x 1\n", "newmtl a\nNs\n", "newmtl a\nillum -1\n"] {
+            let result = parse_mtl_file(&write_test_file("mtl-malformed.mtl", contents.as_bytes()));
+            assert!(result.is_err(), "{:?}", contents);
+        }
+    }
+}
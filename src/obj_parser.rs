@@ -1,124 +1,535 @@
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Error};
-use std::mem::size_of;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::str::SplitWhitespace;
-use gl::types::GLushort;
+use gl::types::GLuint;
 
-use crate::models::obj_data::{ObjData, Vertex, Face};
+use crate::models::material::{Material, MaterialRange};
+use crate::models::obj_data::{ObjData, Vertex, Face, FaceAttributes, FaceIndex};
+use crate::models::obj_parse_error::{ObjParseError, ObjParseErrorKind};
+use crate::models::parse_options::ParseOptions;
+use crate::models::sub_mesh::SubMesh;
 use crate::models::vec3::Vec3;
+use crate::freeform::{self, Spline};
+use crate::globals::MAX_TESSELLATION_RESOLUTION;
+use crate::mapped_file::MappedFile;
+use crate::mtl_parser;
+use crate::obj_tokenizer::{self, Statement, Statements};
+use crate::triangulation;
 
 
-pub fn parse_obj_file(file_path: &str) -> Result<ObjData, Error> {
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
+// files are cut in chunks of at least this size to be parsed on several threads
+const PARALLEL_CHUNK_SIZE: usize = 1 << 20;
 
-    let mut vertices = Vec::new();
+// statements an obj file can start with, used to recognise one whatever its extension
+const STATEMENT_KEYWORDS: [&str; 18] = ["v", "vt", "vn", "vp", "f", "l", "p", "o", "g", "s", "mtllib", "usemtl", "cstype", "deg", "curv", "surf", "parm", "end"];
+
+// number of v, vt and vn read so far, used to resolve negative (relative) indices
+#[derive(Debug, Clone, Copy, Default)]
+struct ElementCounts {
+    positions: usize,
+    texture_coords: usize,
+    normals: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CurveType {
+    Bezier,
+    BSpline,
+}
+
+// a curv or surf statement waiting for its parm statements and its end
+struct FreeFormElement {
+    surface: bool,
+    range_u: (f32, f32),
+    range_v: (f32, f32),
+    control: Vec<GLuint>,
+    parameters_u: Vec<f32>,
+    parameters_v: Vec<f32>,
+    attributes: FaceAttributes,
+    line_number: usize,
+}
+
+// set by cstype and deg, and shared by the curves and surfaces that follow
+struct FreeFormState {
+    curve_type: Option<CurveType>,
+    rational: bool,
+    degree: (usize, usize),
+    element: Option<FreeFormElement>,
+}
+
+// tessellated vertices are only added after the ones of the file so they don't shift its indices,
+// the faces and lines using them are offset once the whole file is read
+struct Tessellation {
+    positions: Vec<Vec3>,
+    texture_coords: Vec<(f32, f32)>,
+    faces: Vec<usize>,
+    lines: Vec<usize>,
+}
+
+impl Tessellation {
+    fn append_to(self, positions: &mut Vec<Vec3>, colors: &mut Vec<Option<Vec3>>, texture_coords: &mut Vec<(f32, f32)>, faces: &mut [Face], lines: &mut [Face]) {
+        let position_offset = positions.len() as GLuint;
+        let texture_offset = texture_coords.len() as GLuint;
+        let offset = |element: &mut Face| {
+            for index in element.indices.iter_mut() {
+                index.vertex += position_offset;
+                index.texture = index.texture.map(|texture| texture + texture_offset);
+            }
+        };
+        self.faces.iter().for_each(|&face| offset(&mut faces[face]));
+        self.lines.iter().for_each(|&line| offset(&mut lines[line]));
+        colors.extend(std::iter::repeat_n(None, self.positions.len()));
+        positions.extend(self.positions);
+        texture_coords.extend(self.texture_coords);
+    }
+}
+
+// work done on a statement ahead of the sequential pass, in parallel over the chunks of the file
+enum PreParsed<'a> {
+    Nothing,
+    Vertex(Result<(Vec3, Option<Vec3>, Option<f32>), TokenError<'a>>),
+    TextureCoord(Result<(f32, f32), TokenError<'a>>),
+    Normal(Result<Vec3, TokenError<'a>>),
+    Face(Result<Vec<FaceIndex>, TokenError<'a>>),
+}
+
+type PreParsedChunk<'a> = Vec<(Statement<'a>, PreParsed<'a>)>;
+
+// error raised by a statement parser, the token is a slice of the statement so its line and column can be found
+struct TokenError<'a> {
+    kind: ObjParseErrorKind,
+    token: &'a str,
+}
+
+impl<'a> TokenError<'a> {
+    fn new(kind: ObjParseErrorKind, token: &'a str) -> Self {
+        TokenError {kind, token}
+    }
+
+    fn missing(expected: &'static str) -> Self {
+        TokenError {kind: ObjParseErrorKind::MissingValue(expected), token: ""}
+    }
+}
+
+// returns the mesh along with the problems that were worked around in lenient mode
+pub fn parse_obj_file(file_path: &str, options: &ParseOptions) -> Result<(ObjData, Vec<ObjParseError>), ObjParseError> {
+    let file = MappedFile::open(file_path).map_err(|e| ObjParseError::io(file_path, e))?;
+    // small files are a single chunk
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    parse_obj_chunks(file_path, &file, (file.len() / PARALLEL_CHUNK_SIZE).clamp(1, threads), options)
+}
+
+// the content of the file is cut into about chunk_count chunks, parsed on as many threads
+fn parse_obj_chunks(file_path: &str, bytes: &[u8], chunk_count: usize, options: &ParseOptions) -> Result<(ObjData, Vec<ObjParseError>), ObjParseError> {
+
+    let mut positions = Vec::new();
+    // the optional "v x y z r g b" colour of each position
+    let mut colors: Vec<Option<Vec3>> = Vec::new();
+    // the optional "v x y z w" weight of each position, used by rational curves and surfaces
+    let mut weights: Vec<f32> = Vec::new();
+    // a weight is only meaningful on the control points of a rational curve or surface, the others are
+    // reported once the whole file is read: (position, error)
+    let mut unused_weights: Vec<(GLuint, ObjParseError)> = Vec::new();
+    let mut rational_controls: HashSet<GLuint> = HashSet::new();
+    let mut texture_coords = Vec::new();
+    let mut normals = Vec::new();
     let mut faces = Vec::new();
-    let mut num_vertices: u32 = 0;
+    // polylines are split into two-vertex segments, and "p" statements into single vertices
+    let mut lines = Vec::new();
+    let mut points = Vec::new();
+    let mut materials: Vec<Material> = Vec::new();
+    let mut material_libraries: Vec<String> = Vec::new();
+    // faces before the first "o" or "g" go to an unnamed sub-mesh
+    let mut sub_meshes: Vec<SubMesh> = vec![SubMesh::new("", "", 0)];
+    let mut object = String::new();
+    // faces are smoothed unless the file says otherwise ("s off" or "s 0"), hard edges are kept by the crease angle
+    let mut attributes = FaceAttributes {smoothing_group: 1, material: None};
+    let mut warnings: Vec<ObjParseError> = Vec::new();
+    // unknown statements are only reported once each
+    let mut unknown_statements: HashSet<String> = HashSet::new();
+    let mut free_form = FreeFormState {curve_type: None, rational: false, degree: (1, 1), element: None};
+    let mut tessellation = Tessellation {positions: Vec::new(), texture_coords: Vec::new(), faces: Vec::new(), lines: Vec::new()};
 
-    for line in reader.lines() {
-        let line = line?;
-        let mut parts = line.trim().split_whitespace();
+    for (statement, pre_parsed) in pre_parse(bytes, chunk_count).into_iter().flatten() {
+        let line_number = statement.line_number();
+        let mut parts = statement.tokens();
+        let keyword = parts.next();
+        let mut line_warnings: Vec<TokenError> = Vec::new();
+        let counts = ElementCounts {
+            positions: positions.len(),
+            texture_coords: texture_coords.len(),
+            normals: normals.len(),
+        };
 
-        match parts.next() {
+        let result = match keyword {
             Some("v") => {
-                add_vertex(&mut vertices, &mut parts, num_vertices)?;
-                num_vertices += 1;
+                let vertex = match pre_parsed {
+                    PreParsed::Vertex(vertex) => vertex,
+                    _ => parse_vertex(&mut parts),
+                };
+                vertex.map(|(position, color, weight)| {
+                    if weight.is_some() {
+                        let token = statement.tokens().nth(4).unwrap_or("");
+                        let error = TokenError::new(ObjParseErrorKind::UnsupportedWeight, token);
+                        unused_weights.push((positions.len() as GLuint + 1, located_error(file_path, &statement, error)));
+                    }
+                    positions.push(position);
+                    colors.push(color);
+                    weights.push(weight.unwrap_or(1.0));
+                })
+            }
+            Some("vt") => {
+                let texture_coord = match pre_parsed {
+                    PreParsed::TextureCoord(texture_coord) => texture_coord,
+                    _ => parse_texture_coord(&mut parts),
+                };
+                texture_coord.map(|texture_coord| texture_coords.push(texture_coord))
+            }
+            Some("vn") => {
+                let normal = match pre_parsed {
+                    PreParsed::Normal(normal) => normal,
+                    _ => parse_normal(&mut parts),
+                };
+                normal.map(|normal| normals.push(normal))
+            }
+            Some("s") => parse_smoothing_group(&mut parts).map(|group| attributes.smoothing_group = group),
+            Some("o") => {
+                object = parts.collect::<Vec<&str>>().join(" ");
+                sub_meshes.push(SubMesh::new(&object, "", faces.len() * 3));
+                Ok(())
+            }
+            Some("g") => {
+                // a face listed in several groups ends up in a sub-mesh named after all of them
+                let group = parts.collect::<Vec<&str>>().join(" ");
+                sub_meshes.push(SubMesh::new(&object, &group, faces.len() * 3));
+                Ok(())
+            }
+            Some("mtllib") => {
+                let libraries = (&mut materials, &mut material_libraries);
+                load_material_libraries(libraries, &mut parts, file_path, options, &mut line_warnings)
             }
+            Some("usemtl") => match find_material(&materials, &mut parts) {
+                Ok(material) => {
+                    attributes.material = material;
+                    Ok(())
+                }
+                // an unknown material still ends the previous one, even when it's only a warning
+                Err(error) => {
+                    attributes.material = None;
+                    Err(error)
+                }
+            },
             Some("f") => {
-                add_face(&mut faces, &mut parts)?;
+                let indices = match pre_parsed {
+                    PreParsed::Face(indices) => indices,
+                    _ => parse_face_indices(&mut parts, &counts),
+                };
+                indices.and_then(|indices| add_face(&mut faces, indices, attributes, &positions, line_number))
             }
-            _ => {}
+            Some("l") => add_line(&mut lines, &mut parts, attributes, &counts, line_number),
+            Some("p") => add_points(&mut points, &mut parts, attributes, &counts, line_number),
+            Some("cstype") => parse_curve_type(&mut free_form, &mut parts),
+            Some("deg") => parse_degree(&mut parts).map(|degree| free_form.degree = degree),
+            Some(keyword @ ("curv" | "surf")) => start_free_form(&mut free_form, &mut parts, keyword, attributes, &counts, line_number),
+            Some(keyword @ "parm") => add_parameters(&mut free_form, &mut parts, keyword),
+            Some(keyword @ "end") => {
+                if let Some(element) = free_form.element.as_ref().filter(|_| free_form.rational) {
+                    rational_controls.extend(&element.control);
+                }
+                let output = (&mut tessellation, &mut faces, &mut lines);
+                finish_free_form(&mut free_form, keyword, &positions, &weights, output, options, &mut line_warnings)
+            }
+            Some(keyword) if unknown_statements.contains(keyword) => Ok(()),
+            Some(keyword) => {
+                unknown_statements.insert(keyword.to_string());
+                Err(TokenError::new(ObjParseErrorKind::UnknownStatement, keyword))
+            }
+            None => Ok(()),
+        };
+
+        match result {
+            Ok(()) => {}
+            Err(e) if options.is_lenient() => {
+                // the line is skipped, but v, vt and vn still take their slot so later indices stay right.
+                // the NaN placeholders are caught by the mesh validation
+                match keyword {
+                    Some("v") => {
+                        positions.push(Vec3::new(f32::NAN, f32::NAN, f32::NAN));
+                        colors.push(None);
+                        weights.push(1.0);
+                    }
+                    Some("vt") => texture_coords.push((f32::NAN, f32::NAN)),
+                    Some("vn") => normals.push(Vec3::new(f32::NAN, f32::NAN, f32::NAN)),
+                    _ => {}
+                }
+                line_warnings.push(e);
+            }
+            Err(e) => return Err(located_error(file_path, &statement, e)),
+        }
+        for warning in line_warnings {
+            warnings.push(located_error(file_path, &statement, warning));
+        }
+    }
+    if let Some(element) = free_form.element {
+        let kind = ObjParseErrorKind::InvalidFreeForm("the curve or surface has no end statement");
+        let error = ObjParseError::new(file_path, element.line_number, 0, "", kind);
+        if !options.is_lenient() {
+            return Err(error);
         }
+        warnings.push(error);
     }
-    let indices = get_indices_array_from_faces(&faces);
-    let num_indices = indices.len();
-    let vertices_raw = get_vertices_array(&vertices);
-    let vertex_buffer_size = vertices_raw.len() * size_of::<f32>();
-    let indices_buffer_size = indices.len() * size_of::<u16>();
-    let (center_x, center_y, center_z, longest_distance) = get_center_and_size(&vertices);
-
-    Ok(ObjData {
-        vertices,
-        num_vertices,
-        vertices_raw,
-        vertex_buffer_size,
-        indices,
-        num_indices,
-        indices_buffer_size,
-        center_x,
-        center_y,
-        center_z,
-        longest_distance,
-        orientation_x: 0.0,
-        orientation_y: 0.0,
-        orientation_z: 0.0,
-        position_x: 0.0,
-        position_y: 0.0,
-        position_z: 0.0,
-        scale_x: 1.0,
-        scale_y: 1.0,
-        scale_z: 1.0,
-        display_texture: false,
+    for (position, error) in unused_weights {
+        if rational_controls.contains(&position) {
+            continue;
+        }
+        if !options.is_lenient() {
+            return Err(error);
+        }
+        warnings.push(error);
+    }
+    tessellation.append_to(&mut positions, &mut colors, &mut texture_coords, &mut faces, &mut lines);
+
+    let (vertices, [indices, line_indices, point_indices], reference_errors) =
+        build_vertex_buffer(&positions, &colors, &texture_coords, &normals, [&faces, &lines, &points]);
+    for (line_number, index, kind) in reference_errors {
+        let error = ObjParseError::new(file_path, line_number, 0, &index.to_string(), kind);
+        if !options.is_lenient() {
+            return Err(error);
+        }
+        warnings.push(error);
+    }
+    let smoothing_groups = faces.iter().map(|face| face.attributes.smoothing_group).collect();
+    let material_ranges = get_material_ranges(&faces);
+    let sub_meshes = finish_sub_meshes(sub_meshes, faces.len() * 3);
+
+    let has_vertex_colors = colors.iter().any(|color| color.is_some());
+    let mut obj_data = ObjData::new(vertices, indices, smoothing_groups);
+    obj_data.materials = materials;
+    obj_data.dependencies = material_libraries;
+    obj_data.material_ranges = material_ranges;
+    obj_data.sub_meshes = sub_meshes;
+    obj_data.has_vertex_colors = has_vertex_colors;
+    obj_data.set_lines_and_points(line_indices, point_indices);
+    Ok((obj_data, warnings))
+}
+
+// number and index parsing, the slow part of reading a large file, runs on several threads:
+// a first pass tokenizes each chunk and parses its v, vt and vn, then once the number of elements
+// before each chunk is known a second pass parses the face indices. statements joined from several
+// lines don't borrow the file and are left to the sequential pass
+fn pre_parse(bytes: &[u8], chunk_count: usize) -> Vec<PreParsedChunk<'_>> {
+    let chunks = obj_tokenizer::split_chunks(bytes, chunk_count);
+
+    let tokenized: Vec<(PreParsedChunk, usize, ElementCounts)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = chunks.iter().map(|&chunk| scope.spawn(move || tokenize_chunk(chunk))).collect();
+        handles.into_iter().map(|handle| handle.join().expect("parser thread panicked")).collect()
+    });
+
+    let mut first_line = 0;
+    let mut counts = ElementCounts::default();
+    std::thread::scope(|scope| {
+        let mut handles = Vec::new();
+        for (statements, num_lines, chunk_counts) in tokenized {
+            let start = counts;
+            handles.push(scope.spawn(move || resolve_faces(statements, first_line, start)));
+            first_line += num_lines;
+            counts.positions += chunk_counts.positions;
+            counts.texture_coords += chunk_counts.texture_coords;
+            counts.normals += chunk_counts.normals;
+        }
+        handles.into_iter().map(|handle| handle.join().expect("parser thread panicked")).collect()
     })
 }
 
-fn add_vertex(vertices: &mut Vec<Vertex>, parts: &mut SplitWhitespace, index: u32) -> Result<(), Error> {
-    let x = parts.next().ok_or_else(|| Error::new(io::ErrorKind::InvalidData, "Invalid vertex format"))?;
-    let y = parts.next().ok_or_else(|| Error::new(io::ErrorKind::InvalidData, "Invalid vertex format"))?;
-    let z = parts.next().ok_or_else(|| Error::new(io::ErrorKind::InvalidData, "Invalid vertex format"))?;
+// returns the statements of the chunk with their line number in the chunk, its number of lines and its element counts
+fn tokenize_chunk(chunk: &[u8]) -> (PreParsedChunk<'_>, usize, ElementCounts) {
+    let mut statements = Statements::new(chunk);
+    let mut pre_parsed = Vec::new();
+    let mut counts = ElementCounts::default();
+    for statement in statements.by_ref() {
+        match statement.tokens().next() {
+            Some("v") => counts.positions += 1,
+            Some("vt") => counts.texture_coords += 1,
+            Some("vn") => counts.normals += 1,
+            _ => {}
+        }
+        let mut parts = match statement.borrowed_tokens() {
+            Some(parts) => parts,
+            None => {
+                pre_parsed.push((statement, PreParsed::Nothing));
+                continue;
+            }
+        };
+        let parsed = match parts.next() {
+            Some("v") => PreParsed::Vertex(parse_vertex(&mut parts)),
+            Some("vt") => PreParsed::TextureCoord(parse_texture_coord(&mut parts)),
+            Some("vn") => PreParsed::Normal(parse_normal(&mut parts)),
+            _ => PreParsed::Nothing,
+        };
+        pre_parsed.push((statement, parsed));
+    }
+    (pre_parsed, statements.lines_read(), counts)
+}
 
-    if parts.next().is_some() {
-        return Err(Error::new(io::ErrorKind::InvalidData, "A vertex must have exactly 3 coordinates"));
+fn resolve_faces(mut statements: PreParsedChunk<'_>, first_line: usize, mut counts: ElementCounts) -> PreParsedChunk<'_> {
+    for (statement, pre_parsed) in statements.iter_mut() {
+        statement.shift_lines(first_line);
+        match statement.tokens().next() {
+            Some("v") => counts.positions += 1,
+            Some("vt") => counts.texture_coords += 1,
+            Some("vn") => counts.normals += 1,
+            Some("f") => {
+                if let Some(mut parts) = statement.borrowed_tokens() {
+                    parts.next();
+                    *pre_parsed = PreParsed::Face(parse_face_indices(&mut parts, &counts));
+                }
+            }
+            _ => {}
+        }
     }
-    
-    let xfloat = match x.parse::<f32>() {
-        Ok(value) => value,
-        Err(e) => return Err(Error::new(io::ErrorKind::InvalidData, e)),
-    };
-    let yfloat = match y.parse::<f32>() {
-        Ok(value) => value,
-        Err(e) => return Err(Error::new(io::ErrorKind::InvalidData, e)),
-    };
-    let zfloat = match z.parse::<f32>() {
-        Ok(value) => value,
-        Err(e) => return Err(Error::new(io::ErrorKind::InvalidData, e)),
-    };
-    
-    let position = Vec3::new(xfloat, yfloat, zfloat);
-    let rgb = generate_random_color(index);
-    let text_x: f32 = zfloat;
-    let text_y: f32 = yfloat;
-    vertices.push(Vertex {position, rgb, text_x, text_y});
+    statements
+}
 
-    Ok(())
+fn located_error(file_path: &str, statement: &Statement, error: TokenError) -> ObjParseError {
+    let (line_number, column) = statement.locate(error.token);
+    ObjParseError::new(file_path, line_number, column, error.token, error.kind)
 }
 
-fn add_face(faces: &mut Vec<Face>, parts: &mut SplitWhitespace) -> Result<(), Error> {
-    let mut indices: Vec<GLushort> = Vec::new();
+fn parse_float(token: &str) -> Result<f32, TokenError<'_>> {
+    token.parse::<f32>().map_err(|_| TokenError::new(ObjParseErrorKind::InvalidNumber, token))
+}
+
+// the first statement of the file, comments and blank lines aside, has to be an obj one
+pub fn looks_like_obj(head: &[u8]) -> bool {
+    let keyword = Statements::new(head).find_map(|statement| statement.tokens().next().map(str::to_string));
+    keyword.is_some_and(|keyword| STATEMENT_KEYWORDS.contains(&keyword.as_str()))
+}
 
-    for (_i, s) in parts.enumerate() {
-        let index = match s.parse::<GLushort>() {
-            Ok(value) => {
-                if value == 0 {
-                    return Err(Error::new(io::ErrorKind::InvalidData, "Invalid vertex index (0)"));
+// each sub-mesh runs until the next one starts, those without any face are dropped
+pub fn finish_sub_meshes(mut sub_meshes: Vec<SubMesh>, num_indices: usize) -> Vec<SubMesh> {
+    for i in 0..sub_meshes.len() {
+        let end = sub_meshes.get(i + 1).map_or(num_indices, |next| next.first_index);
+        sub_meshes[i].num_indices = end - sub_meshes[i].first_index;
+    }
+    sub_meshes.retain(|sub_mesh| sub_mesh.num_indices > 0);
+    sub_meshes
+}
+
+// in lenient mode a missing or broken material library doesn't prevent the geometry from being displayed
+fn load_material_libraries<'a>((materials, paths): (&mut Vec<Material>, &mut Vec<String>), parts: &mut SplitWhitespace<'a>, obj_path: &str, options: &ParseOptions, warnings: &mut Vec<TokenError<'a>>) -> Result<(), TokenError<'a>> {
+    let directory = Path::new(obj_path).parent().unwrap_or(Path::new(""));
+    for file_name in parts {
+        let mtl_path = directory.join(file_name).to_string_lossy().into_owned();
+        let library = mtl_parser::parse_mtl_file(&mtl_path);
+        paths.push(mtl_path);
+        match library {
+            Ok(library) => materials.extend(library),
+            Err(e) => {
+                let error = TokenError::new(ObjParseErrorKind::MaterialLibrary(e.to_string()), file_name);
+                if !options.is_lenient() {
+                    return Err(error);
                 }
-                value
-            },
-            Err(e) => return Err(Error::new(io::ErrorKind::InvalidData, e)),
-        };
-        indices.push(index);
+                warnings.push(error);
+            }
+        }
+    }
+    Ok(())
+}
+
+// faces using an unknown material are drawn with the default look
+fn find_material<'a>(materials: &[Material], parts: &mut SplitWhitespace<'a>) -> Result<Option<usize>, TokenError<'a>> {
+    let name = parts.next().ok_or_else(|| TokenError::missing("material name"))?;
+    match materials.iter().position(|material| material.name == name) {
+        Some(material) => Ok(Some(material)),
+        None => Err(TokenError::new(ObjParseErrorKind::UnknownMaterial, name)),
+    }
+}
+
+// every face is a triangle at this point, so it spans exactly 3 indices
+fn get_material_ranges(faces: &[Face]) -> Vec<MaterialRange> {
+    let mut ranges: Vec<MaterialRange> = Vec::new();
+
+    for (i, face) in faces.iter().enumerate() {
+        match ranges.last_mut() {
+            Some(range) if range.material == face.attributes.material => range.num_indices += 3,
+            _ => ranges.push(MaterialRange {
+                material: face.attributes.material,
+                first_index: i * 3,
+                num_indices: 3,
+            }),
+        }
+    }
+    ranges
+}
+
+// "v x y z" can be followed by either a weight or an r g b colour written by scanning tools.
+// the weight only matters for rational curves and surfaces
+fn parse_vertex<'a>(parts: &mut SplitWhitespace<'a>) -> Result<(Vec3, Option<Vec3>, Option<f32>), TokenError<'a>> {
+    let x = parts.next().ok_or_else(|| TokenError::missing("vertex x coordinate"))?;
+    let y = parts.next().ok_or_else(|| TokenError::missing("vertex y coordinate"))?;
+    let z = parts.next().ok_or_else(|| TokenError::missing("vertex z coordinate"))?;
+    let position = Vec3::new(parse_float(x)?, parse_float(y)?, parse_float(z)?);
+
+    let (color, weight) = match [parts.next(), parts.next(), parts.next(), parts.next()] {
+        [None, ..] => (None, None),
+        [Some(w), None, ..] => (None, Some(parse_float(w)?)),
+        [Some(_), Some(_), None, _] => return Err(TokenError::missing("vertex blue component")),
+        [Some(r), Some(g), Some(b), None] => (Some(Vec3::new(parse_float(r)?, parse_float(g)?, parse_float(b)?)), None),
+        [.., Some(extra)] => return Err(TokenError::new(ObjParseErrorKind::UnexpectedValue("a vertex must have 3 coordinates followed by a weight or an r g b colour"), extra)),
+    };
+
+    Ok((position, color, weight))
+}
+
+fn parse_texture_coord<'a>(parts: &mut SplitWhitespace<'a>) -> Result<(f32, f32), TokenError<'a>> {
+    let u = parts.next().ok_or_else(|| TokenError::missing("texture coordinate u"))?;
+    let v = parts.next().unwrap_or("0.0");
+    let _w = parts.next();
+
+    if let Some(extra) = parts.next() {
+        return Err(TokenError::new(ObjParseErrorKind::UnexpectedValue("a texture coordinate must have at most 3 components"), extra));
     }
+
+    Ok((parse_float(u)?, parse_float(v)?))
+}
+
+fn parse_normal<'a>(parts: &mut SplitWhitespace<'a>) -> Result<Vec3, TokenError<'a>> {
+    let x = parts.next().ok_or_else(|| TokenError::missing("normal x component"))?;
+    let y = parts.next().ok_or_else(|| TokenError::missing("normal y component"))?;
+    let z = parts.next().ok_or_else(|| TokenError::missing("normal z component"))?;
+
+    if let Some(extra) = parts.next() {
+        return Err(TokenError::new(ObjParseErrorKind::UnexpectedValue("a normal must have exactly 3 components"), extra));
+    }
+
+    Ok(Vec3::new(parse_float(x)?, parse_float(y)?, parse_float(z)?))
+}
+
+fn parse_face_indices<'a>(parts: &mut SplitWhitespace<'a>, counts: &ElementCounts) -> Result<Vec<FaceIndex>, TokenError<'a>> {
+    parts.map(|s| parse_face_index(s, counts)).collect()
+}
+
+fn add_face<'a>(faces: &mut Vec<Face>, indices: Vec<FaceIndex>, attributes: FaceAttributes, positions: &[Vec3], line_number: usize) -> Result<(), TokenError<'a>> {
     if indices.len() < 3 {
-        return Err(Error::new(io::ErrorKind::InvalidData, "A face must have at least 3 indices"))
+        return Err(TokenError::new(ObjParseErrorKind::NotEnoughFaceVertices, ""));
     }
     else if indices.len() == 3 {
-        faces.push(Face {indices});
+        faces.push(Face {indices, attributes, line_number});
     }
     else {
-        for i in 2..indices.len() {
+        // a corner referencing a vertex that isn't read yet can't be projected, those polygons are fanned
+        let points: Option<Vec<Vec3>> = indices.iter()
+            .map(|index| positions.get(index.vertex as usize - 1).copied())
+            .collect();
+        let triangles = match points {
+            Some(points) => triangulation::triangulate(&points),
+            None => (2..indices.len()).map(|i| [0, i - 1, i]).collect(),
+        };
+        for [a, b, c] in triangles {
             let face = Face {
-                indices: vec![indices[0], indices[i -1], indices[i]],
+                indices: vec![indices[a], indices[b], indices[c]],
+                attributes,
+                line_number,
             };
             faces.push(face);
         }
@@ -126,65 +537,533 @@ fn add_face(faces: &mut Vec<Face>, parts: &mut SplitWhitespace) -> Result<(), Er
     Ok(())
 }
 
-fn get_indices_array_from_faces (faces: &Vec<Face>) -> Vec<GLushort> {
-    let mut res = Vec::new();
-    for face in faces {
-        for &index in &face.indices {
-            res.push(index - 1); // indices start from 1 in obj file, they need to start from 0 in openGL buffer
-        }
+fn add_line<'a>(lines: &mut Vec<Face>, parts: &mut SplitWhitespace<'a>, attributes: FaceAttributes, counts: &ElementCounts, line_number: usize) -> Result<(), TokenError<'a>> {
+    let mut indices: Vec<FaceIndex> = Vec::new();
+
+    for s in parts {
+        indices.push(parse_face_index(s, counts)?);
+    }
+    if indices.len() < 2 {
+        return Err(TokenError::new(ObjParseErrorKind::NotEnoughLineVertices, ""));
+    }
+    for segment in indices.windows(2) {
+        lines.push(Face {indices: segment.to_vec(), attributes, line_number});
     }
-    res
+    Ok(())
 }
 
-fn get_vertices_array(vertices: &Vec<Vertex>) -> Vec<f32> {
-    let mut vertices_raw = Vec::with_capacity(vertices.len() * 8);
+fn add_points<'a>(points: &mut Vec<Face>, parts: &mut SplitWhitespace<'a>, attributes: FaceAttributes, counts: &ElementCounts, line_number: usize) -> Result<(), TokenError<'a>> {
+    let mut indices: Vec<FaceIndex> = Vec::new();
 
-    for vertex in vertices {
-        vertices_raw.push(vertex.position.x);
-        vertices_raw.push(vertex.position.y);
-        vertices_raw.push(vertex.position.z);
-        vertices_raw.push(vertex.rgb.x);
-        vertices_raw.push(vertex.rgb.y);
-        vertices_raw.push(vertex.rgb.z);
-        vertices_raw.push(vertex.text_x);
-        vertices_raw.push(vertex.text_y);
+    for s in parts {
+        indices.push(parse_face_index(s, counts)?);
+    }
+    if indices.is_empty() {
+        return Err(TokenError::missing("point vertex"));
     }
+    for index in indices {
+        points.push(Face {indices: vec![index], attributes, line_number});
+    }
+    Ok(())
+}
 
-    vertices_raw
+// "cstype [rat] type", only bezier and b-spline (nurbs when rational) curves are supported
+fn parse_curve_type<'a>(free_form: &mut FreeFormState, parts: &mut SplitWhitespace<'a>) -> Result<(), TokenError<'a>> {
+    free_form.curve_type = None;
+    let mut curve_type = parts.next().ok_or_else(|| TokenError::missing("curve type"))?;
+    free_form.rational = curve_type == "rat";
+    if free_form.rational {
+        curve_type = parts.next().ok_or_else(|| TokenError::missing("curve type"))?;
+    }
+    free_form.curve_type = match curve_type {
+        "bezier" => Some(CurveType::Bezier),
+        "bspline" => Some(CurveType::BSpline),
+        _ => return Err(TokenError::new(ObjParseErrorKind::UnsupportedCurveType, curve_type)),
+    };
+    Ok(())
+}
+
+// "deg u [v]", the v degree only matters for surfaces and defaults to the u one
+fn parse_degree<'a>(parts: &mut SplitWhitespace<'a>) -> Result<(usize, usize), TokenError<'a>> {
+    let parse = |token: &'a str| {
+        token.parse::<usize>().ok()
+            .filter(|&degree| (1..=freeform::MAX_DEGREE).contains(&degree))
+            .ok_or(TokenError::new(ObjParseErrorKind::InvalidFreeForm("the degree must be an integer from 1 to 16"), token))
+    };
+    let u = parse(parts.next().ok_or_else(|| TokenError::missing("curve degree"))?)?;
+    let v = match parts.next() {
+        Some(token) => parse(token)?,
+        None => u,
+    };
+    Ok((u, v))
+}
+
+// "curv u0 u1 v1 v2 ..." and "surf s0 s1 t0 t1 v1 v2 ...", the vt and vn of the control points are ignored.
+// a curve or surface left without an end statement is dropped
+fn start_free_form<'a>(free_form: &mut FreeFormState, parts: &mut SplitWhitespace<'a>, keyword: &'a str, attributes: FaceAttributes, counts: &ElementCounts, line_number: usize) -> Result<(), TokenError<'a>> {
+    let surface = keyword == "surf";
+    let unfinished = free_form.element.take().is_some();
+    let mut parameter = || parts.next().ok_or_else(|| TokenError::missing("parameter range")).and_then(parse_float);
+    let range_u = (parameter()?, parameter()?);
+    let range_v = if surface { (parameter()?, parameter()?) } else { (0.0, 0.0) };
+    let mut control = Vec::new();
+    for token in parts {
+        control.push(parse_face_index(token, counts)?.vertex);
+    }
+    if control.is_empty() {
+        return Err(TokenError::missing("control point"));
+    }
+
+    free_form.element = Some(FreeFormElement {
+        surface,
+        range_u,
+        range_v,
+        control,
+        parameters_u: Vec::new(),
+        parameters_v: Vec::new(),
+        attributes,
+        line_number,
+    });
+    if unfinished {
+        return Err(TokenError::new(ObjParseErrorKind::InvalidFreeForm("the previous curve or surface has no end statement"), keyword));
+    }
+    Ok(())
 }
 
-fn get_center_and_size(vertices: &Vec<Vertex>) -> (f32, f32, f32, f32) {
-    let mut min_x = std::f32::MAX;
-    let mut max_x = std::f32::MIN;
-    let mut min_y = std::f32::MAX;
-    let mut max_y = std::f32::MIN;
-    let mut min_z = std::f32::MAX;
-    let mut max_z = std::f32::MIN;
-    let mut longest = std::f32::MIN;
+// "parm u|v p1 p2 ...", the knot vector of a b-spline or where the segments of a bezier meet
+fn add_parameters<'a>(free_form: &mut FreeFormState, parts: &mut SplitWhitespace<'a>, keyword: &'a str) -> Result<(), TokenError<'a>> {
+    let element = free_form.element.as_mut()
+        .ok_or(TokenError::new(ObjParseErrorKind::InvalidFreeForm("parm outside of a curve or surface"), keyword))?;
+    let direction = parts.next().ok_or_else(|| TokenError::missing("parameter direction"))?;
+    let values = parts.map(parse_float).collect::<Result<Vec<f32>, TokenError>>()?;
+    match direction {
+        "u" => element.parameters_u.extend(values),
+        "v" if element.surface => element.parameters_v.extend(values),
+        _ => return Err(TokenError::new(ObjParseErrorKind::InvalidFreeForm("the direction must be u, or v for a surface"), direction)),
+    }
+    Ok(())
+}
+
+// "end" tessellates the pending curve into line segments, or surface into triangles
+fn finish_free_form<'a>(free_form: &mut FreeFormState, keyword: &'a str, positions: &[Vec3], weights: &[f32], output: (&mut Tessellation, &mut Vec<Face>, &mut Vec<Face>), options: &ParseOptions, warnings: &mut Vec<TokenError<'a>>) -> Result<(), TokenError<'a>> {
+    let (tessellation, faces, lines) = output;
+    let invalid = |reason: &'static str| TokenError::new(ObjParseErrorKind::InvalidFreeForm(reason), keyword);
+    let element = free_form.element.take().ok_or(invalid("end without a curve or surface"))?;
+    let curve_type = free_form.curve_type.ok_or(invalid("no supported cstype was given"))?;
+
+    let spline_u = make_spline(curve_type, free_form.degree.0, element.parameters_u, element.range_u);
+    let spline_v = make_spline(curve_type, free_form.degree.1, element.parameters_v, element.range_v);
+    let u_count = spline_u.control_point_count().ok_or(invalid("the u parameters don't fit the degree"))?;
+    let v_count = match element.surface {
+        true => spline_v.control_point_count().ok_or(invalid("the v parameters don't fit the degree"))?,
+        false => 1,
+    };
+    if u_count * v_count != element.control.len() {
+        return Err(invalid("the number of control points doesn't match the degree and parameters"));
+    }
+    let control: Vec<Vec3> = element.control.iter()
+        .map(|&index| positions.get(index as usize - 1).copied())
+        .collect::<Option<Vec<Vec3>>>()
+        .ok_or(TokenError::new(ObjParseErrorKind::IndexOutOfRange("vertex"), keyword))?;
+    let control_weights: Vec<f32> = match free_form.rational {
+        true => element.control.iter().map(|&index| weights[index as usize - 1]).collect(),
+        false => vec![1.0; control.len()],
+    };
+
+    // a huge --resolution would tessellate every surface into millions of triangles
+    let mut resolution = options.resolution;
+    if resolution > MAX_TESSELLATION_RESOLUTION {
+        let error = TokenError::new(ObjParseErrorKind::Unsupported(format!("a tessellation resolution above {}", MAX_TESSELLATION_RESOLUTION)), keyword);
+        if !options.is_lenient() {
+            return Err(error);
+        }
+        warnings.push(error);
+        resolution = MAX_TESSELLATION_RESOLUTION;
+    }
+
+    // indices are relative to the tessellated vertices until Tessellation::append_to
+    let first_position = tessellation.positions.len() as GLuint + 1;
+    let first_texture = tessellation.texture_coords.len() as GLuint + 1;
+    if element.surface {
+        let surface = freeform::tessellate_surface(&spline_u, &spline_v, &control, &control_weights, resolution);
+        tessellation.positions.extend(surface.points);
+        tessellation.texture_coords.extend(surface.texture_coords);
+        for triangle in surface.triangles {
+            let indices = triangle.iter()
+                .map(|&i| FaceIndex {vertex: first_position + i as GLuint, texture: Some(first_texture + i as GLuint), normal: None})
+                .collect();
+            tessellation.faces.push(faces.len());
+            faces.push(Face {indices, attributes: element.attributes, line_number: element.line_number});
+        }
+    } else {
+        let points = freeform::tessellate_curve(&spline_u, &control, &control_weights, resolution);
+        let count = points.len() as GLuint;
+        tessellation.positions.extend(points);
+        for i in 0..count - 1 {
+            let indices = vec![
+                FaceIndex {vertex: first_position + i, texture: None, normal: None},
+                FaceIndex {vertex: first_position + i + 1, texture: None, normal: None},
+            ];
+            tessellation.lines.push(lines.len());
+            lines.push(Face {indices, attributes: element.attributes, line_number: element.line_number});
+        }
+    }
+    Ok(())
+}
+
+// a bezier without parm statement is a single segment over its range
+fn make_spline(curve_type: CurveType, degree: usize, parameters: Vec<f32>, range: (f32, f32)) -> Spline {
+    match curve_type {
+        CurveType::Bezier if parameters.is_empty() => Spline::bezier(degree, &[range.0, range.1], range),
+        CurveType::Bezier => Spline::bezier(degree, &parameters, range),
+        CurveType::BSpline => Spline {degree, knots: parameters, range},
+    }
+}
+
+// "s off" and "s 0" both disable smoothing
+fn parse_smoothing_group<'a>(parts: &mut SplitWhitespace<'a>) -> Result<u32, TokenError<'a>> {
+    let group = parts.next().ok_or_else(|| TokenError::missing("smoothing group"))?;
+    if group == "off" {
+        return Ok(0);
+    }
+    group.parse::<u32>().map_err(|_| TokenError::new(ObjParseErrorKind::InvalidSmoothingGroup, group))
+}
+
+// accepts the four obj face vertex forms: v, v/vt, v//vn and v/vt/vn
+fn parse_face_index<'a>(token: &'a str, counts: &ElementCounts) -> Result<FaceIndex, TokenError<'a>> {
+    let fields: Vec<&str> = token.split('/').collect();
+    if fields.len() > 3 {
+        return Err(TokenError::new(ObjParseErrorKind::InvalidFaceVertex, token));
+    }
+
+    let vertex = parse_index(fields[0], counts.positions)?;
+    let texture = match fields.get(1) {
+        Some(&"") if fields.len() == 3 => None,
+        Some(&"") => return Err(TokenError::new(ObjParseErrorKind::InvalidFaceVertex, token)),
+        Some(s) => Some(parse_index(s, counts.texture_coords)?),
+        None => None,
+    };
+    let normal = match fields.get(2) {
+        Some(&"") => return Err(TokenError::new(ObjParseErrorKind::InvalidFaceVertex, token)),
+        Some(s) => Some(parse_index(s, counts.normals)?),
+        None => None,
+    };
+
+    Ok(FaceIndex {vertex, texture, normal})
+}
 
-    for vertex in vertices {
-        if vertex.position.x < min_x { min_x = vertex.position.x; }
-        if vertex.position.x > max_x { max_x = vertex.position.x; }
-        if vertex.position.y < min_y { min_y = vertex.position.y; }
-        if vertex.position.y > max_y { max_y = vertex.position.y; }
-        if vertex.position.z < min_z { min_z = vertex.position.z; }
-        if vertex.position.z > max_z { max_z = vertex.position.z; }
+// negative indices count back from the last element read, -1 being the most recent one
+fn parse_index(s: &str, count: usize) -> Result<GLuint, TokenError<'_>> {
+    match s.parse::<i64>() {
+        Ok(value) => {
+            if value == 0 {
+                return Err(TokenError::new(ObjParseErrorKind::ZeroIndex, s));
+            }
+            let resolved = if value < 0 { count as i64 + value + 1 } else { value };
+            if resolved < 1 {
+                return Err(TokenError::new(ObjParseErrorKind::RelativeIndexOutOfRange, s));
+            }
+            if resolved > GLuint::MAX as i64 {
+                return Err(TokenError::new(ObjParseErrorKind::InvalidIndex, s));
+            }
+            Ok(resolved as GLuint)
+        },
+        Err(_) => Err(TokenError::new(ObjParseErrorKind::InvalidIndex, s)),
     }
-    let center_x = (min_x + max_x) / 2.0;
-    let center_y = (min_y + max_y) / 2.0;
-    let center_z = (min_z + max_z) / 2.0;
-    let obj_max_width = max_x - min_x;
-    let obj_max_height = max_y - min_y;
-    let obj_max_depth = max_z - min_z;
+}
 
-    if obj_max_width > longest {longest = obj_max_width;}
-    if obj_max_height > longest {longest = obj_max_height;}
-    if obj_max_depth > longest {longest = obj_max_depth;}
+// a face, line or point referencing an element that doesn't exist: its line, the index at fault and why
+type ReferenceError = (usize, GLuint, ObjParseErrorKind);
 
-    (center_x, center_y, center_z, longest)
+// every unique (position, uv, normal) combination referenced by a face, a line or a point becomes one
+// gpu vertex, shared by the index lists of the triangles, line segments and points.
+// references to elements that don't exist are returned with the line of the face and the index at fault,
+// the corner then points past the end of the vertex buffer so the mesh validation drops its element
+fn build_vertex_buffer<const N: usize>(positions: &[Vec3], colors: &[Option<Vec3>], texture_coords: &[(f32, f32)], normals: &[Vec3], element_lists: [&[Face]; N]) -> (Vec<Vertex>, [Vec<GLuint>; N], Vec<ReferenceError>) {
+    let mut vertices = Vec::new();
+    let mut errors = Vec::new();
+    let mut unique: HashMap<FaceIndex, GLuint> = HashMap::new();
+
+    let index_lists = element_lists.map(|elements| {
+        let mut indices = Vec::new();
+        for face in elements {
+            for face_index in &face.indices {
+                let index = match unique.get(face_index) {
+                    Some(&index) => index,
+                    None => match build_vertex(positions, colors, texture_coords, normals, face_index) {
+                        Ok(vertex) => {
+                            let index = vertices.len() as GLuint;
+                            vertices.push(vertex);
+                            unique.insert(*face_index, index);
+                            index
+                        }
+                        Err((index, kind)) => {
+                            errors.push((face.line_number, index, kind));
+                            GLuint::MAX
+                        }
+                    },
+                };
+                indices.push(index);
+            }
+        }
+        indices
+    });
+    (vertices, index_lists, errors)
 }
 
-fn generate_random_color(index: u32) -> Vec3 {
+fn build_vertex(positions: &[Vec3], colors: &[Option<Vec3>], texture_coords: &[(f32, f32)], normals: &[Vec3], face_index: &FaceIndex) -> Result<Vertex, (GLuint, ObjParseErrorKind)> {
+    // indices start from 1 in obj file, they need to start from 0 in openGL buffer
+    let position_index = face_index.vertex as usize - 1;
+    let position = *positions.get(position_index)
+        .ok_or((face_index.vertex, ObjParseErrorKind::IndexOutOfRange("vertex")))?;
+    // models without vt data fall back to a planar (z, y) projection
+    let (text_x, text_y) = match face_index.texture {
+        Some(texture) => *texture_coords.get(texture as usize - 1)
+            .ok_or((texture, ObjParseErrorKind::IndexOutOfRange("texture coordinate")))?,
+        None => (position.z, position.y),
+    };
+    // faces without vn get a zero normal for now
+    let normal = match face_index.normal {
+        Some(normal) => *normals.get(normal as usize - 1)
+            .ok_or((normal, ObjParseErrorKind::IndexOutOfRange("normal")))?,
+        None => Vec3::new(0.0, 0.0, 0.0),
+    };
+    // positions without a colour keep the shades of gray that tell the faces apart
+    let rgb = colors[position_index].unwrap_or_else(|| generate_random_color(position_index as u32));
+    Ok(Vertex {position, rgb, text_x, text_y, normal, properties: Vec::new()})
+}
+
+pub fn generate_random_color(index: u32) -> Vec3 {
     let gray = ((index * 15451) % 255) as f32 / 255.0;
     Vec3::new(gray, gray, gray)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{write_test_file, LENIENT, STRICT};
+
+    fn parse(name: &str, contents: &str, options: &ParseOptions) -> Result<(ObjData, Vec<ObjParseError>), ObjParseError> {
+        parse_obj_file(&write_test_file(name, contents.as_bytes()), options)
+    }
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+
+    #[test]
+    fn face_vertex_forms_share_vertices() {
+        let contents = format!("{}vt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 1\nf 1 2 3\nf 1/1 2/2 3/3\nf 1//1 2//1 3//1\nf 1/1/1 2/2/1 3/3/1\nf 1 2 3\n", TRIANGLE);
+        let (obj_data, warnings) = parse("obj-forms", &contents, &STRICT).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(obj_data.num_indices, 15);
+        // the last face repeats the first one, every other face brings its own combinations
+        assert_eq!(obj_data.vertices.len(), 12);
+        assert_eq!(obj_data.indices.get(12), obj_data.indices.get(0));
+        let second = &obj_data.vertices[obj_data.indices.get(4) as usize];
+        assert_eq!((second.text_x, second.text_y), (1.0, 0.0));
+        let third = &obj_data.vertices[obj_data.indices.get(8) as usize];
+        assert_eq!((third.normal.z, third.text_x, third.text_y), (1.0, third.position.z, third.position.y));
+    }
+
+    #[test]
+    fn malformed_face_vertices_are_refused() {
+        for face in ["f 1/ 2/ 3/", "f 1/1/1/1 2 3", "f 1//  2 3", "f 0 1 2", "f a 2 3", "f 1/x 2 3"] {
+            let error = parse("obj-bad-face", &format!("{}{}\n", TRIANGLE, face), &STRICT).err();
+            assert!(error.is_some(), "{}", face);
+        }
+        // a face needs 3 corners, lines and points have their own statements
+        let error = parse("obj-two-corners", &format!("{}f 1 2\n", TRIANGLE), &STRICT).err().unwrap();
+        assert_eq!(error.kind, ObjParseErrorKind::NotEnoughFaceVertices);
+        let (obj_data, warnings) = parse("obj-two-corners", &format!("{}f 1 2\nf 1 2 3\n", TRIANGLE), &LENIENT).unwrap();
+        assert_eq!((warnings.len(), obj_data.num_indices), (1, 3));
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_element() {
+        // the second face comes after a fourth vertex, so -1 is vertex 4 there
+        let contents = format!("{}vt 0 0\nvt 1 1\nf -3/-2 -2/-1 -1/-1\nv 1 1 0\nf -3 -2 -1\n", TRIANGLE);
+        let (obj_data, warnings) = parse("obj-negative", &contents, &STRICT).unwrap();
+        assert!(warnings.is_empty());
+        let position = |corner: usize| obj_data.vertices[obj_data.indices.get(corner) as usize].position;
+        assert_eq!((position(0).x, position(2).y), (0.0, 1.0));
+        assert_eq!((position(3).x, position(4).y, position(5).x), (1.0, 1.0, 1.0));
+        let first = &obj_data.vertices[obj_data.indices.get(0) as usize];
+        assert_eq!((first.text_x, first.text_y), (0.0, 0.0));
+    }
+
+    #[test]
+    fn negative_indices_before_their_elements_are_refused() {
+        let error = parse("obj-negative-range", &format!("{}f -4 -2 -1\n", TRIANGLE), &STRICT).err().unwrap();
+        assert_eq!((error.kind, error.token.as_str()), (ObjParseErrorKind::RelativeIndexOutOfRange, "-4"));
+        let error = parse("obj-negative-zero", &format!("{}f -0 -2 -1\n", TRIANGLE), &STRICT).err().unwrap();
+        assert_eq!(error.kind, ObjParseErrorKind::ZeroIndex);
+        // a relative texture index needs texture coordinates read before it
+        let error = parse("obj-negative-vt", &format!("{}f 1/-1 2/-1 3/-1\nvt 0 0\n", TRIANGLE), &STRICT).err().unwrap();
+        assert_eq!(error.kind, ObjParseErrorKind::RelativeIndexOutOfRange);
+    }
+
+    #[test]
+    fn usemtl_splits_the_faces_into_material_ranges() {
+        write_test_file("obj-ranges.mtl", b"newmtl a\nKd 1 0 0\nnewmtl b\nKd 0 1 0\n");
+        let contents = format!("mtllib obj-ranges.mtl\n{}f 1 2 3\nusemtl b\nf 1 2 3\nf 1 2 3\nusemtl a\nf 1 2 3\n", TRIANGLE);
+        let (obj_data, warnings) = parse("obj-ranges", &contents, &STRICT).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(obj_data.materials.len(), 2);
+        let ranges: Vec<_> = obj_data.material_ranges.iter()
+            .map(|range| (range.material, range.first_index, range.num_indices))
+            .collect();
+        assert_eq!(ranges, [(None, 0, 3), (Some(1), 3, 6), (Some(0), 9, 3)]);
+    }
+
+    #[test]
+    fn o_and_g_start_sub_meshes() {
+        let contents = format!("{}v 1 1 0\nf 1 2 3\no cube\ng empty\ng top side\nf 1 2 4 3\ng\nf 1 2 3\no other\nf 1 2 3\n", TRIANGLE);
+        let (obj_data, warnings) = parse("obj-sub-meshes", &contents, &STRICT).unwrap();
+        assert!(warnings.is_empty());
+        let sub_meshes: Vec<_> = obj_data.sub_meshes.iter()
+            .map(|sub_mesh| (sub_mesh.name(), sub_mesh.first_index, sub_mesh.num_indices))
+            .collect();
+        // the empty group is dropped, the triangulated quad spans 6 indices
+        assert_eq!(sub_meshes, [
+            ("default".to_string(), 0, 3),
+            ("cube/top side".to_string(), 3, 6),
+            ("cube".to_string(), 9, 3),
+            ("other".to_string(), 12, 3),
+        ]);
+    }
+
+    #[test]
+    fn errors_point_at_their_token() {
+        let error = parse("obj-location", "# header\nv 0 0 0\n\nv 1  x 0\n", &STRICT).err().unwrap();
+        assert_eq!((error.line_number, error.column, error.token.as_str()), (4, 6, "x"));
+        assert_eq!(error.kind, ObjParseErrorKind::InvalidNumber);
+        // a missing value points just past the end of the line
+        let error = parse("obj-missing", "v 1 2\n", &STRICT).err().unwrap();
+        assert_eq!((error.line_number, error.column), (1, 6));
+    }
+
+    #[test]
+    fn lenient_mode_skips_bad_lines_but_keeps_their_slot() {
+        let contents = "v 0 0 0\nv x 0 0\nv 1 0 0\nv 0 1 0\nfoo 1\nfoo 2\nf 1 3 4\n";
+        assert_eq!(parse("obj-slot", contents, &STRICT).err().unwrap().kind, ObjParseErrorKind::InvalidNumber);
+        let (obj_data, warnings) = parse("obj-slot", contents, &LENIENT).unwrap();
+        // the unknown statement is only reported once
+        let kinds: Vec<_> = warnings.iter().map(|warning| (warning.line_number, warning.kind.clone())).collect();
+        assert_eq!(kinds, [(2, ObjParseErrorKind::InvalidNumber), (5, ObjParseErrorKind::UnknownStatement)]);
+        let position = |corner: usize| obj_data.vertices[obj_data.indices.get(corner) as usize].position;
+        assert_eq!((position(0).x, position(1).x, position(2).y), (0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn missing_materials_fall_back_to_the_default_look() {
+        write_test_file("obj-unknown.mtl", b"newmtl known\n");
+        let contents = format!("mtllib obj-unknown.mtl missing.mtl\n{}usemtl known\nf 1 2 3\nusemtl unknown\nf 1 2 3\n", TRIANGLE);
+        let error = parse("obj-unknown-material", &contents, &STRICT).err().unwrap();
+        assert_eq!((error.line_number, error.token.as_str()), (1, "missing.mtl"));
+        let (obj_data, warnings) = parse("obj-unknown-material", &contents, &LENIENT).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[1].kind, ObjParseErrorKind::UnknownMaterial);
+        // the unknown material ends the previous one instead of extending it
+        let materials: Vec<_> = obj_data.material_ranges.iter().map(|range| range.material).collect();
+        assert_eq!(materials, [Some(0), None]);
+    }
+
+    #[test]
+    fn polylines_are_split_into_segments() {
+        let contents = format!("{}vt 0 0\nl 1 2 3 1\nl 1/1 2/1\np 3 -1\n", TRIANGLE);
+        let (obj_data, warnings) = parse("obj-lines", &contents, &STRICT).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!((obj_data.num_indices, obj_data.line_indices.len(), obj_data.point_indices.len()), (0, 8, 2));
+        let x = |index: GLuint| obj_data.vertices[index as usize].position.x;
+        let line: Vec<f32> = obj_data.line_indices[..6].iter().map(|&index| x(index)).collect();
+        assert_eq!(line, [0.0, 1.0, 1.0, 0.0, 0.0, 0.0]);
+        // the same position with a texture coordinate is another vertex
+        assert_ne!(obj_data.line_indices[6], obj_data.line_indices[0]);
+        assert_eq!(obj_data.point_indices[0], obj_data.point_indices[1]);
+
+        let error = parse("obj-short-line", &format!("{}l 1\n", TRIANGLE), &STRICT).err().unwrap();
+        assert_eq!(error.kind, ObjParseErrorKind::NotEnoughLineVertices);
+    }
+
+    // a quadratic bezier curve through the first and last vertex, then a bilinear patch over all four
+    const FREE_FORM: &str = "cstype bezier\ndeg 2\ncurv 0 1 1 2 3\nend\ndeg 1 1\nsurf 0 1 0 1 1 2 3 4\nend\n";
+
+    #[test]
+    fn free_forms_are_tessellated() {
+        let options = ParseOptions {resolution: 4, ..STRICT};
+        let (obj_data, warnings) = parse("obj-free-form", &format!("{}v 1 1 0\n{}", TRIANGLE, FREE_FORM), &options).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(obj_data.line_indices.len(), 4 * 2);
+        assert_eq!(obj_data.num_indices, 4 * 4 * 2 * 3);
+    }
+
+    #[test]
+    fn weights_only_on_rational_control_points() {
+        let weighted = "v 0 0 0 2\nv 1 0 0\nv 0 1 0\nv 1 1 0\n";
+        let error = parse("obj-weights", &format!("{}{}", weighted, FREE_FORM), &STRICT).err().unwrap();
+        assert_eq!((error.kind, error.line_number, error.column), (ObjParseErrorKind::UnsupportedWeight, 1, 9));
+        let (_, warnings) = parse("obj-weights", &format!("{}{}", weighted, FREE_FORM), &LENIENT).unwrap();
+        assert_eq!(warnings.len(), 1);
+
+        let rational = FREE_FORM.replace("cstype bezier", "cstype rat bezier");
+        let (_, warnings) = parse("obj-rational", &format!("{}{}", weighted, rational), &STRICT).unwrap();
+        assert!(warnings.is_empty());
+        // the weight of a vertex that no rational element uses is still refused
+        let unused = format!("{}v 0 0 1 0.5\n{}", weighted, rational);
+        let error = parse("obj-rational-unused", &unused, &STRICT).err().unwrap();
+        assert_eq!((error.kind, error.line_number), (ObjParseErrorKind::UnsupportedWeight, 5));
+    }
+
+    #[test]
+    fn free_form_bounds() {
+        let error = parse("obj-degree", "cstype bspline\ndeg 17\n", &STRICT).err().unwrap();
+        assert!(matches!(error.kind, ObjParseErrorKind::InvalidFreeForm(_)));
+
+        let contents = format!("{}v 1 1 0\n{}", TRIANGLE, FREE_FORM);
+        let options = ParseOptions {resolution: MAX_TESSELLATION_RESOLUTION + 1, ..STRICT};
+        let error = parse("obj-resolution", &contents, &options).err().unwrap();
+        assert!(matches!(error.kind, ObjParseErrorKind::Unsupported(_)));
+        // lenient mode clamps the resolution instead
+        let options = ParseOptions {resolution: options.resolution, ..LENIENT};
+        let (obj_data, warnings) = parse("obj-resolution", &contents, &options).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(obj_data.line_indices.len(), MAX_TESSELLATION_RESOLUTION as usize * 2);
+    }
+
+    // every kind of line a chunk boundary could fall on: a comment ending with a backslash, crlf pairs,
+    // continued statements, relative indices and lines that only give warnings
+    const CHUNKED: &str = "# header \\\nv 0 0 0\r\nv 1 0 \\\r\n  0\r\nv 0 1 0 # third\r\nvt 0 0\nvn 0 0 1\n\
+        f 1/1/1 2/1/1 \\\n3/1/1\nv 1 1 0\nf -4 -3 -1 -2\nv x 0 0\nl 1 2 \\\n5 -1\nf 1 2 99\n";
+
+    // vertices, indices, number of triangle indices and warnings
+    type Summary = (Vec<[u32; 8]>, Vec<GLuint>, usize, Vec<(usize, usize, String, ObjParseErrorKind)>);
+
+    // what the parse produced, with the floats as bits so the NaN placeholders compare equal
+    fn summary(result: (ObjData, Vec<ObjParseError>)) -> Summary {
+        let (obj_data, warnings) = result;
+        let vertices = obj_data.vertices.iter()
+            .map(|v| [v.position.x, v.position.y, v.position.z, v.text_x, v.text_y, v.normal.x, v.normal.y, v.normal.z].map(f32::to_bits))
+            .collect();
+        let indices = (0..obj_data.indices.len()).map(|i| obj_data.indices.get(i)).collect();
+        let warnings = warnings.into_iter().map(|w| (w.line_number, w.column, w.token, w.kind)).collect();
+        (vertices, indices, obj_data.num_indices, warnings)
+    }
+
+    #[test]
+    fn chunked_parse_matches_the_sequential_one() {
+        let contents = format!("{}{}{}p 1 \\", CHUNKED, CHUNKED, CHUNKED);
+        let path = write_test_file("obj-chunks", contents.as_bytes());
+        let sequential = summary(parse_obj_chunks(&path, contents.as_bytes(), 1, &LENIENT).unwrap());
+        assert_eq!(sequential.3.len(), 3 * 2);
+        let mut most_chunks = 1;
+        for chunk_count in 2..=contents.lines().count() {
+            most_chunks = most_chunks.max(obj_tokenizer::split_chunks(contents.as_bytes(), chunk_count).len());
+            let chunked = parse_obj_chunks(&path, contents.as_bytes(), chunk_count, &LENIENT).unwrap();
+            assert_eq!(summary(chunked), sequential, "{} chunks", chunk_count);
+        }
+        // continued lines are never split from the next one
+        assert!(most_chunks > contents.lines().count() / 2 && most_chunks <= contents.lines().count() - 3 * 3);
+
+        let error = parse_obj_chunks(&path, contents.as_bytes(), 7, &STRICT).err().unwrap();
+        assert_eq!((error.line_number, error.column), (12, 3));
+    }
+}
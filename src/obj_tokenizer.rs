@@ -0,0 +1,235 @@
+use std::borrow::Cow;
+use std::str::SplitWhitespace;
+
+// one statement of an obj or mtl file: a physical line, or several of them when they end with a backslash.
+// comments are already removed and the tokens are slices of the text, so they can be traced back to
+// the line and column they come from. the text borrows the file unless lines had to be joined
+pub struct Statement<'a> {
+    text: Cow<'a, str>,
+    first_line: usize,
+    // offset in text where each continuation line starts, they follow the first line
+    continuations: Vec<usize>,
+}
+
+impl<'a> Statement<'a> {
+    pub fn tokens(&self) -> SplitWhitespace<'_> {
+        self.text.split_whitespace()
+    }
+
+    // tokens that outlive the statement, only for statements that borrow the file
+    pub fn borrowed_tokens(&self) -> Option<SplitWhitespace<'a>> {
+        match self.text {
+            Cow::Borrowed(text) => Some(text.split_whitespace()),
+            Cow::Owned(_) => None,
+        }
+    }
+
+    pub fn line_number(&self) -> usize {
+        self.first_line
+    }
+
+    // statements of a chunk are numbered from its start, this moves them to their place in the file
+    pub fn shift_lines(&mut self, offset: usize) {
+        self.first_line += offset;
+    }
+
+    // line and column of a token of this statement.
+    // a missing value has no token and is reported just after the end of the statement
+    pub fn locate(&self, token: &str) -> (usize, usize) {
+        let text_start = self.text.as_ptr() as usize;
+        let token_start = token.as_ptr() as usize;
+        let offset = if token.is_empty() || token_start < text_start || token_start > text_start + self.text.len() {
+            self.text.trim_end().len()
+        } else {
+            token_start - text_start
+        };
+        let continued = self.continuations.iter().take_while(|&&line_start| line_start <= offset).count();
+        let line_start = if continued == 0 { 0 } else { self.continuations[continued - 1] };
+        (self.first_line + continued, self.text[line_start..offset].chars().count() + 1)
+    }
+}
+
+// splits the content of a file into statements following the obj grammar:
+//  - lines end with \n or \r\n, a stray \r is whitespace like tabs
+//  - everything after a # is a comment
+//  - a backslash at the end of a line joins it with the next one
+//  - bytes that aren't valid utf-8 (latin-1 names from old exporters) are replaced rather than refused
+pub struct Statements<'a> {
+    bytes: &'a [u8],
+    line_number: usize,
+}
+
+impl<'a> Statements<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Statements {bytes, line_number: 0}
+    }
+
+    // number of physical lines read so far
+    pub fn lines_read(&self) -> usize {
+        self.line_number
+    }
+
+    fn next_line(&mut self) -> Option<Cow<'a, str>> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        let (line, rest) = split_line(self.bytes);
+        self.bytes = rest;
+        self.line_number += 1;
+        let line = String::from_utf8_lossy(line);
+        Some(match line {
+            Cow::Borrowed(text) => Cow::Borrowed(strip_comment(text)),
+            Cow::Owned(text) => Cow::Owned(strip_comment(&text).to_string()),
+        })
+    }
+}
+
+impl<'a> Iterator for Statements<'a> {
+    type Item = Statement<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.next_line()?;
+        let mut statement = Statement {text: line, first_line: self.line_number, continuations: Vec::new()};
+        while let Some(continued) = statement.text.trim_end().strip_suffix('\\') {
+            let mut text = continued.to_string();
+            text.push(' ');
+            // a backslash on the last line continues into nothing
+            let Some(line) = self.next_line() else {
+                statement.text = Cow::Owned(text);
+                break;
+            };
+            statement.continuations.push(text.len());
+            text.push_str(&line);
+            statement.text = Cow::Owned(text);
+        }
+        Some(statement)
+    }
+}
+
+// cuts the content of a file into about count chunks that can be tokenized on their own:
+// every chunk ends on a line that doesn't continue into the next one
+pub fn split_chunks(bytes: &[u8], count: usize) -> Vec<&[u8]> {
+    let mut chunks = Vec::with_capacity(count);
+    let mut rest = bytes;
+    for remaining in (1..=count).rev() {
+        let mut end = rest.len() / remaining;
+        if remaining > 1 {
+            // move to the next line start, then past the lines that are continued
+            loop {
+                match rest[end..].iter().position(|&b| b == b'\n') {
+                    Some(newline) => end += newline + 1,
+                    None => end = rest.len(),
+                }
+                if end == rest.len() || !continues(line_before(&rest[..end])) {
+                    break;
+                }
+            }
+        } else {
+            end = rest.len();
+        }
+        let (chunk, tail) = rest.split_at(end);
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+        rest = tail;
+    }
+    chunks
+}
+
+fn split_line(bytes: &[u8]) -> (&[u8], &[u8]) {
+    let (line, rest) = match bytes.iter().position(|&b| b == b'\n') {
+        Some(newline) => (&bytes[..newline], &bytes[newline + 1..]),
+        None => (bytes, &bytes[bytes.len()..]),
+    };
+    (line.strip_suffix(b"\r").unwrap_or(line), rest)
+}
+
+// last line of a chunk ending with a newline
+fn line_before(bytes: &[u8]) -> &[u8] {
+    let bytes = &bytes[..bytes.len() - 1];
+    let start = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |newline| newline + 1);
+    &bytes[start..]
+}
+
+// same test as the statement iterator so chunks are cut exactly where statements end
+fn continues(line: &[u8]) -> bool {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    strip_comment(&String::from_utf8_lossy(line)).trim_end().ends_with('\\')
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(comment) => &line[..comment],
+        None => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statements(bytes: &[u8]) -> Vec<(usize, Vec<String>)> {
+        Statements::new(bytes)
+            .map(|statement| (statement.line_number(), statement.tokens().map(str::to_string).collect()))
+            .collect()
+    }
+
+    fn tokens(list: &[&str]) -> Vec<String> {
+        list.iter().map(|token| token.to_string()).collect()
+    }
+
+    #[test]
+    fn comments_and_line_endings() {
+        let parsed = statements(b"v 1 2 3 # a comment \\\r\n# only a comment\r\nvt\t0\r1\n\nf 1 2 3#4");
+        assert_eq!(parsed, [
+            (1, tokens(&["v", "1", "2", "3"])),
+            (2, Vec::new()),
+            // a stray carriage return is whitespace
+            (3, tokens(&["vt", "0", "1"])),
+            (4, Vec::new()),
+            (5, tokens(&["f", "1", "2", "3"])),
+        ]);
+    }
+
+    #[test]
+    fn backslashes_join_lines() {
+        let parsed = statements(b"f 1 2 \\\r\n  3 \\ # the comment goes first\n4\nv 0 0 0 \\");
+        assert_eq!(parsed, [(1, tokens(&["f", "1", "2", "3", "4"])), (4, tokens(&["v", "0", "0", "0"]))]);
+    }
+
+    #[test]
+    fn tokens_of_continued_lines_are_located_on_their_own_line() {
+        let bytes = b"# header\nf 1 \\\n\t2 \\\n  x\n";
+        let statement = Statements::new(bytes).nth(1).unwrap();
+        let located: Vec<_> = statement.tokens().map(|token| statement.locate(token)).collect();
+        assert_eq!(located, [(2, 1), (2, 3), (3, 2), (4, 3)]);
+        // a missing value is reported after the last token
+        assert_eq!(statement.locate(""), (4, 4));
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        let parsed = statements(b"usemtl caf\xe9\nv 1 2 3\n");
+        assert_eq!(parsed, [(1, tokens(&["usemtl", "caf\u{fffd}"])), (2, tokens(&["v", "1", "2", "3"]))]);
+    }
+
+    #[test]
+    fn chunks_end_where_statements_end() {
+        let bytes = b"v 1 \\\r\n2 3\r\n# not continued \\\nf 1 \\\n2 \\\n3\nv 4 5 6\n";
+        for count in 1..=10 {
+            let chunks = split_chunks(bytes, count);
+            assert!(chunks.len() <= count);
+            assert_eq!(chunks.concat(), bytes);
+            // the statements of the chunks put together are those of the whole file
+            let mut lines = 0;
+            let mut joined = Vec::new();
+            for chunk in &chunks {
+                let mut chunk_statements = statements(chunk);
+                chunk_statements.iter_mut().for_each(|(line, _)| *line += lines);
+                lines += chunk.iter().filter(|&&b| b == b'\n').count();
+                joined.extend(chunk_statements);
+            }
+            assert_eq!(joined, statements(bytes), "{} chunks", count);
+        }
+    }
+}
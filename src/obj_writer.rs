@@ -0,0 +1,158 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Error, Write};
+use std::path::Path;
+
+use crate::models::material::Material;
+use crate::models::obj_data::ObjData;
+
+// writes the mesh as it is after processing, with its materials in a .mtl file next to it.
+// every vertex gets its own v, vt and vn so that parse_obj_file reads back the same vertices and indices.
+// floats are written with the shortest representation that parses back to the same value
+pub fn write_obj_file(obj_data: &ObjData, file_path: &str) -> Result<(), Error> {
+    let path = Path::new(file_path);
+    let mut out = BufWriter::new(File::create(path)?);
+
+    if !obj_data.materials.is_empty() {
+        let mtl_path = path.with_extension("mtl");
+        write_mtl_file(&obj_data.materials, &mtl_path)?;
+        let mtl_name = mtl_path.file_name().unwrap_or_default().to_string_lossy();
+        writeln!(out, "mtllib {}", mtl_name)?;
+    }
+
+    for vertex in &obj_data.vertices {
+        let p = vertex.position;
+        if obj_data.has_vertex_colors {
+            writeln!(out, "v {} {} {} {} {} {}", p.x, p.y, p.z, vertex.rgb.x, vertex.rgb.y, vertex.rgb.z)?;
+        } else {
+            writeln!(out, "v {} {} {}", p.x, p.y, p.z)?;
+        }
+    }
+    for vertex in &obj_data.vertices {
+        writeln!(out, "vt {} {}", vertex.text_x, vertex.text_y)?;
+    }
+    // a zero normal is read back as a missing one and generated again
+    for vertex in &obj_data.vertices {
+        writeln!(out, "vn {} {} {}", vertex.normal.x, vertex.normal.y, vertex.normal.z)?;
+    }
+
+    // the parser starts with smoothing group 1, no material and no object or group
+    let mut smoothing_group = 1;
+    let mut material = None;
+    let mut object = "";
+    for triangle in 0..obj_data.num_indices / 3 {
+        let first_index = triangle * 3;
+        // "o" starts a sub-mesh with no group, "g" one in the current object
+        if let Some(sub_mesh) = obj_data.sub_meshes.iter().find(|sub_mesh| sub_mesh.first_index == first_index) {
+            let unnamed_start = first_index == 0 && sub_mesh.object.is_empty() && sub_mesh.group.is_empty();
+            if sub_mesh.object != object {
+                writeln!(out, "o {}", sub_mesh.object)?;
+                object = &sub_mesh.object;
+                if !sub_mesh.group.is_empty() {
+                    writeln!(out, "g {}", sub_mesh.group)?;
+                }
+            } else if !unnamed_start {
+                writeln!(out, "g {}", sub_mesh.group)?;
+            }
+        }
+        // faces only go back to no material before the first usemtl, which is never written again
+        let range = obj_data.material_ranges.iter().find(|range| range.first_index == first_index);
+        if let Some(&Some(index)) = range.map(|range| &range.material) {
+            if material != Some(index) {
+                writeln!(out, "usemtl {}", obj_data.materials[index].name)?;
+                material = Some(index);
+            }
+        }
+        if obj_data.smoothing_groups[triangle] != smoothing_group {
+            smoothing_group = obj_data.smoothing_groups[triangle];
+            if smoothing_group == 0 {
+                writeln!(out, "s off")?;
+            } else {
+                writeln!(out, "s {}", smoothing_group)?;
+            }
+        }
+        let [a, b, c] = [0, 1, 2].map(|corner| obj_data.indices.get(first_index + corner) + 1);
+        writeln!(out, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+    }
+
+    for segment in obj_data.line_indices.chunks(2) {
+        let [a, b] = [segment[0] + 1, segment[1] + 1];
+        writeln!(out, "l {a}/{a}/{a} {b}/{b}/{b}")?;
+    }
+    for &point in &obj_data.point_indices {
+        let a = point + 1;
+        writeln!(out, "p {a}/{a}/{a}")?;
+    }
+    out.flush()
+}
+
+fn write_mtl_file(materials: &[Material], path: &Path) -> Result<(), Error> {
+    let mut out = BufWriter::new(File::create(path)?);
+    for material in materials {
+        writeln!(out, "newmtl {}", material.name)?;
+        let [ka, kd, ks, ke] = [material.ambient, material.diffuse, material.specular, material.emissive];
+        writeln!(out, "Ka {} {} {}", ka.x, ka.y, ka.z)?;
+        writeln!(out, "Kd {} {} {}", kd.x, kd.y, kd.z)?;
+        writeln!(out, "Ks {} {} {}", ks.x, ks.y, ks.z)?;
+        writeln!(out, "Ke {} {} {}", ke.x, ke.y, ke.z)?;
+        writeln!(out, "Ns {}", material.shininess)?;
+        writeln!(out, "d {}", material.dissolve)?;
+        writeln!(out, "Ni {}", material.optical_density)?;
+        writeln!(out, "illum {}", material.illum)?;
+        let maps = [
+            ("map_Ka", &material.ambient_map),
+            ("map_Kd", &material.diffuse_map),
+            ("map_Ks", &material.specular_map),
+            ("map_d", &material.dissolve_map),
+            ("map_Bump", &material.bump_map),
+        ];
+        for (keyword, map) in maps {
+            if let Some(map) = map {
+                // map paths were resolved against the directory of the original .mtl, an absolute
+                // path keeps them pointing at the same file wherever the copy is written
+                let map = fs::canonicalize(map).map_or_else(|_| map.clone(), |map| map.to_string_lossy().into_owned());
+                writeln!(out, "{} {}", keyword, map)?;
+            }
+        }
+        writeln!(out)?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj_parser::parse_obj_file;
+    use crate::test_support::{test_path, write_test_file, STRICT};
+
+    // everything the writer has to carry over, floats as bits so they have to come back exactly
+    fn contents(obj_data: &ObjData) -> Vec<String> {
+        let mut contents: Vec<String> = obj_data.vertices.iter()
+            .map(|v| format!("{:?}", [v.position.x, v.position.y, v.position.z, v.rgb.x, v.rgb.y, v.rgb.z, v.text_x, v.text_y, v.normal.x, v.normal.y, v.normal.z].map(f32::to_bits)))
+            .collect();
+        contents.push(format!("{:?}", (0..obj_data.indices.len()).map(|i| obj_data.indices.get(i)).collect::<Vec<_>>()));
+        contents.push(format!("{:?} {:?} {:?}", obj_data.num_indices, obj_data.line_indices, obj_data.point_indices));
+        contents.push(format!("{:?}", obj_data.smoothing_groups));
+        contents.extend(obj_data.material_ranges.iter().map(|range| format!("{:?}", range)));
+        contents.extend(obj_data.sub_meshes.iter().map(|sub_mesh| format!("{} {} {}", sub_mesh.name(), sub_mesh.first_index, sub_mesh.num_indices)));
+        contents.extend(obj_data.materials.iter().map(|material| material.to_string()));
+        contents
+    }
+
+    #[test]
+    fn written_files_read_back_the_same() {
+        write_test_file("writer-source.mtl", b"newmtl red\nKd 1 0 0\nNs 10\nnewmtl blue\nKd 0 0 1\nd 0.5\n");
+        let source = "mtllib writer-source.mtl\n\
+            v 0 0 0 1 0 0\nv 1 0 0 0 1 0\nv 1 1 0 0 0 1\nv 0 1 0.1 0.3 0.3 0.3\nv 0.1 0.2 0.3 1 1 1\n\
+            vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 1\n\
+            f 1 2 3\nusemtl red\nf 1/1 3/3 4/2\no part\ng top\ns off\nf 1//1 2//1 3//1 4//1\ng bottom\ns 2\nusemtl blue\nf 3 4 5\n\
+            l 1 2 5\np 5 4\n";
+        let (obj_data, _) = parse_obj_file(&write_test_file("writer-source.obj", source.as_bytes()), &STRICT).unwrap();
+
+        let copy = test_path("writer-copy.obj");
+        write_obj_file(&obj_data, copy.to_str().unwrap()).unwrap();
+        let (read_back, warnings) = parse_obj_file(copy.to_str().unwrap(), &STRICT).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(contents(&read_back), contents(&obj_data));
+        assert!(test_path("writer-copy.mtl").exists());
+    }
+}
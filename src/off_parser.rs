@@ -0,0 +1,333 @@
+use gl::types::GLuint;
+
+use crate::mapped_file::MappedFile;
+use crate::models::obj_data::{ObjData, Vertex};
+use crate::models::obj_parse_error::{ObjParseError, ObjParseErrorKind};
+use crate::models::parse_options::ParseOptions;
+use crate::models::sub_mesh::SubMesh;
+use crate::models::vec3::Vec3;
+use crate::obj_parser::{finish_sub_meshes, generate_random_color};
+use crate::obj_tokenizer::{Statement, Statements};
+use crate::triangulation;
+
+// what the header keyword ([ST][C][N][4][n]OFF) announces on every vertex line.
+// the values follow the coordinates in this order: w, normal, colour, texture coordinates
+struct VertexLayout {
+    texture_coords: bool,
+    colors: bool,
+    normals: bool,
+    homogeneous: bool,
+    // nOFF gives the dimension of the vertices before the counts
+    dimension_given: bool,
+}
+
+// reads geomview object files: OFF and its variants with colours (COFF), normals (NOFF), texture
+// coordinates (STOFF) or homogeneous coordinates (4OFF). faces are polygons of any size, the colour
+// some files put after the indices of a face is ignored since vertices are shared between faces
+pub fn parse_off_file(file_path: &str, options: &ParseOptions) -> Result<(ObjData, Vec<ObjParseError>), ObjParseError> {
+    let file = MappedFile::open(file_path).map_err(|e| ObjParseError::io(file_path, e))?;
+    let mut statements = Statements::new(&file).filter(|statement| statement.tokens().next().is_some());
+    let mut warnings = Vec::new();
+
+    let header = statements.next().ok_or_else(|| malformed(file_path, "the file is empty"))?;
+    let mut parts = header.tokens();
+    let keyword = parts.next().unwrap_or_default();
+    let layout = parse_keyword(keyword)
+        .ok_or_else(|| located_error(file_path, &header, keyword, ObjParseErrorKind::UnexpectedValue("an off file starts with OFF, COFF, NOFF, STOFF or 4OFF")))?;
+    if parts.clone().next() == Some("BINARY") {
+        return Err(ObjParseError::new(file_path, header.line_number(), 0, "", ObjParseErrorKind::Unsupported("binary off files".to_string())));
+    }
+
+    // the counts usually have a line of their own but may follow the keyword, the dimension of nOFF is
+    // often alone on its line
+    let num_values = if layout.dimension_given { 3 } else { 2 };
+    let mut header_statements = vec![header];
+    while header_statements.iter().map(|statement| statement.tokens().count()).sum::<usize>() <= num_values {
+        match statements.next() {
+            Some(statement) => header_statements.push(statement),
+            None => break,
+        }
+    }
+    let values: Vec<(&str, &Statement)> = header_statements.iter()
+        .flat_map(|statement| statement.tokens().map(move |token| (token, statement)))
+        .skip(1)
+        .collect();
+    let (num_vertices, num_faces) = parse_counts(&values, &layout, file_path)?;
+
+    let mut vertices: Vec<Vertex> = Vec::with_capacity(num_vertices.min(file.len()));
+    for _ in 0..num_vertices {
+        let statement = statements.next().ok_or_else(|| malformed(file_path, "the file ends before every vertex is read"))?;
+        let parts: Vec<&str> = statement.tokens().collect();
+        match parse_vertex(&parts, &layout, vertices.len()) {
+            Ok(vertex) => vertices.push(vertex),
+            Err((token, kind)) => {
+                let error = located_error(file_path, &statement, token, kind);
+                if !options.is_lenient() {
+                    return Err(error);
+                }
+                warnings.push(error);
+                // a broken vertex still takes its slot so that the faces keep pointing at the right ones
+                let nan = Vec3::new(f32::NAN, f32::NAN, f32::NAN);
+                vertices.push(Vertex {position: nan, rgb: nan, text_x: 0.0, text_y: 0.0, normal: nan, properties: Vec::new()});
+            }
+        }
+    }
+
+    let mut indices: Vec<GLuint> = Vec::new();
+    for _ in 0..num_faces {
+        let statement = statements.next().ok_or_else(|| malformed(file_path, "the file ends before every face is read"))?;
+        let parts: Vec<&str> = statement.tokens().collect();
+        match parse_face(&parts, &vertices) {
+            Ok(triangles) => indices.extend(triangles),
+            Err((token, kind)) => {
+                let error = located_error(file_path, &statement, token, kind);
+                if !options.is_lenient() {
+                    return Err(error);
+                }
+                warnings.push(error);
+            }
+        }
+    }
+
+    let num_indices = indices.len();
+    let mut obj_data = ObjData::new(vertices, indices, vec![1; num_indices / 3]);
+    obj_data.sub_meshes = finish_sub_meshes(vec![SubMesh::new("", "", 0)], num_indices);
+    obj_data.has_vertex_colors = layout.colors;
+    Ok((obj_data, warnings))
+}
+
+// the first token of the file, after comments, is the keyword of the header
+pub fn looks_like_off(head: &[u8]) -> bool {
+    let keyword = Statements::new(head).find_map(|statement| statement.tokens().next().map(str::to_string));
+    keyword.is_some_and(|keyword| parse_keyword(&keyword).is_some())
+}
+
+fn parse_keyword(keyword: &str) -> Option<VertexLayout> {
+    let mut rest = keyword;
+    let mut prefix = |flag: &str| match rest.strip_prefix(flag) {
+        Some(stripped) => {
+            rest = stripped;
+            true
+        }
+        None => false,
+    };
+    let layout = VertexLayout {
+        texture_coords: prefix("ST"),
+        colors: prefix("C"),
+        normals: prefix("N"),
+        homogeneous: prefix("4"),
+        dimension_given: prefix("n"),
+    };
+    (rest == "OFF").then_some(layout)
+}
+
+// [dimension] vertices faces [edges], the number of edges isn't used by anyone
+fn parse_counts(values: &[(&str, &Statement)], layout: &VertexLayout, file_path: &str) -> Result<(usize, usize), ObjParseError> {
+    let mut values = values.iter();
+    let mut count = || -> Result<(usize, &str, &Statement), ObjParseError> {
+        let Some(&(token, statement)) = values.next() else {
+            return Err(malformed(file_path, "the vertex and face counts are missing"));
+        };
+        let count = token.parse::<usize>().map_err(|_| located_error(file_path, statement, token, ObjParseErrorKind::InvalidNumber))?;
+        Ok((count, token, statement))
+    };
+    if layout.dimension_given {
+        // w isn't counted in the dimension of homogeneous vertices
+        let (dimension, token, statement) = count()?;
+        if dimension != 3 {
+            return Err(located_error(file_path, statement, token, ObjParseErrorKind::Unsupported(format!("{}-dimensional vertices", dimension))));
+        }
+    }
+    Ok((count()?.0, count()?.0))
+}
+
+fn parse_vertex<'a>(parts: &[&'a str], layout: &VertexLayout, index: usize) -> Result<Vertex, (&'a str, ObjParseErrorKind)> {
+    let values = parts.iter()
+        .map(|token| token.parse::<f32>().map_err(|_| (*token, ObjParseErrorKind::InvalidNumber)))
+        .collect::<Result<Vec<f32>, _>>()?;
+    let normal_start = if layout.homogeneous { 4 } else { 3 };
+    let color_start = normal_start + if layout.normals { 3 } else { 0 };
+    let texture_len = if layout.texture_coords { 2 } else { 0 };
+    if values.len() < color_start + texture_len {
+        return Err(("", ObjParseErrorKind::MissingValue("vertex values announced by the header")));
+    }
+    // the colour takes what is left between the normal and the texture coordinates, with or without alpha
+    let color_len = values.len() - color_start - texture_len;
+    match (layout.colors, color_len) {
+        (true, 3 | 4) | (false, 0) => {}
+        (true, _) => return Err((parts.get(color_start).copied().unwrap_or_default(), ObjParseErrorKind::UnexpectedValue("a vertex colour has 3 or 4 components"))),
+        (false, _) => return Err((parts[color_start], ObjParseErrorKind::UnexpectedValue("too many values for the vertex layout of the header"))),
+    }
+    let vec3 = |start: usize| Vec3::new(values[start], values[start + 1], values[start + 2]);
+
+    let mut position = vec3(0);
+    if layout.homogeneous {
+        position = position.scale(1.0 / values[3]);
+    }
+    let normal = if layout.normals { vec3(normal_start) } else { Vec3::new(0.0, 0.0, 0.0) };
+    let rgb = match layout.colors {
+        true => {
+            // colours are either floats from 0 to 1 or integers up to 255
+            let color = vec3(color_start);
+            let scale = if [color.x, color.y, color.z].iter().any(|&value| value > 1.0) { 255.0 } else { 1.0 };
+            color.scale(1.0 / scale)
+        }
+        false => generate_random_color(index as u32),
+    };
+    // same fallback as obj files without texture coordinates
+    let (text_x, text_y) = match layout.texture_coords {
+        true => (values[values.len() - 2], values[values.len() - 1]),
+        false => (position.z, position.y),
+    };
+    Ok(Vertex {position, rgb, text_x, text_y, normal, properties: Vec::new()})
+}
+
+// the number of corners, their indices starting at 0, then an optional colour
+fn parse_face<'a>(parts: &[&'a str], vertices: &[Vertex]) -> Result<Vec<GLuint>, (&'a str, ObjParseErrorKind)> {
+    let count_token = parts[0];
+    let count = count_token.parse::<usize>().map_err(|_| (count_token, ObjParseErrorKind::InvalidNumber))?;
+    if count < 3 {
+        return Err((count_token, ObjParseErrorKind::NotEnoughFaceVertices));
+    }
+    let indices = (1..=count)
+        .map(|i| {
+            let token = *parts.get(i).ok_or(("", ObjParseErrorKind::MissingValue("face vertex index")))?;
+            match token.parse::<usize>() {
+                Ok(index) if index < vertices.len() => Ok(index as GLuint),
+                Ok(_) => Err((token, ObjParseErrorKind::IndexOutOfRange("vertex"))),
+                Err(_) => Err((token, ObjParseErrorKind::InvalidIndex)),
+            }
+        })
+        .collect::<Result<Vec<GLuint>, _>>()?;
+    if count == 3 {
+        return Ok(indices);
+    }
+    let points: Vec<Vec3> = indices.iter().map(|&index| vertices[index as usize].position).collect();
+    Ok(triangulation::triangulate(&points).into_iter().flatten().map(|corner| indices[corner]).collect())
+}
+
+fn malformed(file_path: &str, reason: &'static str) -> ObjParseError {
+    ObjParseError::new(file_path, 0, 0, "", ObjParseErrorKind::Malformed(reason))
+}
+
+fn located_error(file_path: &str, statement: &Statement, token: &str, kind: ObjParseErrorKind) -> ObjParseError {
+    let (line_number, column) = statement.locate(token);
+    ObjParseError::new(file_path, line_number, column, token, kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{write_test_file, LENIENT, STRICT};
+
+    fn parse(name: &str, contents: &str, options: &ParseOptions) -> Result<(ObjData, Vec<ObjParseError>), ObjParseError> {
+        parse_off_file(&write_test_file(name, contents.as_bytes()), options)
+    }
+
+    const SQUARE: &str = "OFF\n# a square\n4 1 0\n0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n";
+
+    #[test]
+    fn polygons_are_triangulated() {
+        let (obj_data, warnings) = parse("off-square", SQUARE, &STRICT).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!((obj_data.vertices.len(), obj_data.num_indices), (4, 6));
+        assert!(!obj_data.has_vertex_colors);
+        // the colour after the indices of a face is skipped
+        let (obj_data, _) = parse("off-face-color", &SQUARE.replace("4 0 1 2 3", "4 0 1 2 3 1 0 0 1"), &STRICT).unwrap();
+        assert_eq!(obj_data.num_indices, 6);
+    }
+
+    #[test]
+    fn header_layouts() {
+        // counts on the keyword line, nOFF dimension alone on its line, integer colours with alpha
+        let cases = [
+            ("OFF 3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n", 0.0),
+            ("nOFF\n3\n3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2 255 0 0\n", 0.0),
+            ("COFF\n3 1 0\n0 0 0 255 0 0 255\n1 0 0 0 255 0 255\n0 1 0 0 0 255 255\n3 0 1 2\n", 1.0),
+            ("STCNOFF\n3 1 0\n0 0 0 0 0 1 0.5 0.5 0.5 0.25 0.75\n1 0 0 0 0 1 1 1 1 0 0\n0 1 0 0 0 1 1 1 1 0 0\n3 0 1 2\n", 0.5),
+            ("4OFF\n3 1 0\n0 0 0 2\n2 0 0 2\n0 2 0 2\n3 0 1 2\n", 0.0),
+        ];
+        for (contents, red) in cases {
+            let (obj_data, warnings) = parse("off-layout", contents, &STRICT).unwrap();
+            assert!(warnings.is_empty(), "{}", contents);
+            assert_eq!(obj_data.num_indices, 3, "{}", contents);
+            assert_eq!(obj_data.vertices[1].position.x, 1.0, "{}", contents);
+            if obj_data.has_vertex_colors {
+                assert_eq!(obj_data.vertices[0].rgb.x, red, "{}", contents);
+            }
+        }
+        let (obj_data, _) = parse("off-texture", cases[3].0, &STRICT).unwrap();
+        assert_eq!((obj_data.vertices[0].text_x, obj_data.vertices[0].text_y, obj_data.vertices[0].normal.z), (0.25, 0.75, 1.0));
+    }
+
+    #[test]
+    fn header_errors() {
+        let error = parse("off-keyword", "# comment\nOFFSET\n3 1 0\n", &LENIENT).err().unwrap();
+        assert!(matches!(error.kind, ObjParseErrorKind::UnexpectedValue(_)));
+        assert_eq!((error.line_number, error.column, error.token.as_str()), (2, 1, "OFFSET"));
+        let error = parse("off-dimension", "nOFF\n4 3 1 0\n", &LENIENT).err().unwrap();
+        assert!(matches!(error.kind, ObjParseErrorKind::Unsupported(_)));
+        assert_eq!((error.line_number, error.column), (2, 1));
+        let error = parse("off-counts", "OFF\n3 one 0\n", &LENIENT).err().unwrap();
+        assert!(matches!(error.kind, ObjParseErrorKind::InvalidNumber));
+        assert_eq!((error.line_number, error.column), (2, 3));
+        let error = parse("off-binary", "OFF BINARY\n", &LENIENT).err().unwrap();
+        assert!(matches!(error.kind, ObjParseErrorKind::Unsupported(_)));
+        for contents in ["", "# nothing but a comment\n", "OFF\n", "OFF 3\n"] {
+            assert!(matches!(parse("off-empty", contents, &LENIENT).err().unwrap().kind, ObjParseErrorKind::Malformed(_)), "{:?}", contents);
+        }
+    }
+
+    #[test]
+    fn truncated_body() {
+        // lenient mode can't make up the missing lines
+        let without_face = SQUARE.replace("4 0 1 2 3\n", "");
+        let error = parse("off-truncated", &without_face, &LENIENT).err().unwrap();
+        assert!(matches!(error.kind, ObjParseErrorKind::Malformed("the file ends before every face is read")));
+        let error = parse("off-truncated", &SQUARE[..SQUARE.len() - 20], &LENIENT).err().unwrap();
+        assert!(matches!(error.kind, ObjParseErrorKind::Malformed("the file ends before every vertex is read")));
+    }
+
+    #[test]
+    fn broken_vertices_keep_their_slot() {
+        let bad_vertex = SQUARE.replace("1 1 0\n", "1 x 0\n");
+        let error = parse("off-vertex", &bad_vertex, &STRICT).err().unwrap();
+        assert_eq!((error.line_number, error.column, error.token.as_str()), (6, 3, "x"));
+        // the face still finds its 4 corners at the indices it gives
+        let (obj_data, warnings) = parse("off-vertex", &bad_vertex, &LENIENT).unwrap();
+        assert_eq!((warnings.len(), obj_data.vertices.len()), (1, 4));
+        assert!(obj_data.vertices[2].position.x.is_nan());
+        assert_eq!(obj_data.vertices[3].position.y, 1.0);
+    }
+
+    #[test]
+    fn vertex_values_follow_the_header() {
+        let missing_color = "COFF\n3 1 0\n0 0 0\n1 0 0 1 1 1\n0 1 0 1 1 1\n3 0 1 2\n";
+        let error = parse("off-color", missing_color, &STRICT).err().unwrap();
+        assert!(matches!(error.kind, ObjParseErrorKind::UnexpectedValue(_)));
+        assert_eq!(error.line_number, 3);
+        let error = parse("off-extra", &SQUARE.replace("1 0 0\n", "1 0 0 1\n"), &STRICT).err().unwrap();
+        assert!(matches!(error.kind, ObjParseErrorKind::UnexpectedValue(_)));
+        assert_eq!((error.line_number, error.column), (5, 7));
+        let missing_normal = "NOFF\n3 1 0\n0 0 0 0 0\n1 0 0 0 0 1\n0 1 0 0 0 1\n3 0 1 2\n";
+        assert!(matches!(parse("off-normal", missing_normal, &STRICT).err().unwrap().kind, ObjParseErrorKind::MissingValue(_)));
+    }
+
+    #[test]
+    fn broken_faces_are_dropped() {
+        let cases = [
+            ("4 0 1 2 9", ObjParseErrorKind::IndexOutOfRange("vertex"), 9),
+            ("2 0 1", ObjParseErrorKind::NotEnoughFaceVertices, 1),
+            ("4 0 1 2", ObjParseErrorKind::MissingValue("face vertex index"), 8),
+            ("x 0 1 2", ObjParseErrorKind::InvalidNumber, 1),
+            ("3 0 -1 2", ObjParseErrorKind::InvalidIndex, 5),
+        ];
+        for (face, kind, column) in cases {
+            let contents = SQUARE.replace("4 0 1 2 3", face);
+            let error = parse("off-face", &contents, &STRICT).err().unwrap();
+            assert_eq!((error.kind, error.line_number, error.column), (kind, 8, column), "{}", face);
+            let (obj_data, warnings) = parse("off-face", &contents, &LENIENT).unwrap();
+            assert_eq!((warnings.len(), obj_data.num_indices), (1, 0), "{}", face);
+        }
+    }
+}
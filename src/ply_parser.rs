@@ -0,0 +1,559 @@
+use gl::types::GLuint;
+
+use crate::mapped_file::MappedFile;
+use crate::models::obj_data::{ObjData, Vertex};
+use crate::models::obj_parse_error::{ObjParseError, ObjParseErrorKind};
+use crate::models::parse_options::ParseOptions;
+use crate::models::sub_mesh::SubMesh;
+use crate::models::vec3::Vec3;
+use crate::obj_parser::{finish_sub_meshes, generate_random_color};
+use crate::obj_tokenizer::{Statement, Statements};
+use crate::triangulation;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    // both the names of the original specification and the sized ones are in use
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(ScalarType::Int8),
+            "uchar" | "uint8" => Some(ScalarType::UInt8),
+            "short" | "int16" => Some(ScalarType::Int16),
+            "ushort" | "uint16" => Some(ScalarType::UInt16),
+            "int" | "int32" => Some(ScalarType::Int32),
+            "uint" | "uint32" => Some(ScalarType::UInt32),
+            "float" | "float32" => Some(ScalarType::Float32),
+            "double" | "float64" => Some(ScalarType::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+
+    // integer colours go from 0 to the largest value of their type, float ones from 0 to 1
+    fn color_scale(self) -> f64 {
+        match self {
+            ScalarType::Int8 => i8::MAX as f64,
+            ScalarType::UInt8 => u8::MAX as f64,
+            ScalarType::Int16 => i16::MAX as f64,
+            ScalarType::UInt16 => u16::MAX as f64,
+            ScalarType::Int32 => i32::MAX as f64,
+            ScalarType::UInt32 => u32::MAX as f64,
+            ScalarType::Float32 | ScalarType::Float64 => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PropertyType {
+    Scalar(ScalarType),
+    // type of the item count, then of the items
+    List(ScalarType, ScalarType),
+}
+
+struct Property {
+    name: String,
+    kind: PropertyType,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+    // number of lines, the ascii records are numbered after them
+    lines: usize,
+}
+
+// value of each property of one element, lists are kept aside in the order they appear.
+// the line is 0 in binary files
+struct Record {
+    scalars: Vec<f64>,
+    lists: Vec<Vec<f64>>,
+    line_number: usize,
+}
+
+// where each vertex attribute is found among the properties of the vertex element
+struct VertexLayout {
+    position: [usize; 3],
+    normal: Option<[usize; 3]>,
+    texture_coord: Option<[usize; 2]>,
+    color: Option<([usize; 3], f64)>,
+    // the scalar properties that aren't one of the above
+    extra: Vec<usize>,
+}
+
+// reads stanford ply files, ascii or binary in either byte order. the vertex element gives the positions
+// and, when present, the normals (nx ny nz), texture coordinates (s t, u v...) and colours (red green blue),
+// every other scalar vertex property is kept in Vertex::properties. faces are lists of any size, other
+// elements are read past and ignored
+pub fn parse_ply_file(file_path: &str, options: &ParseOptions) -> Result<(ObjData, Vec<ObjParseError>), ObjParseError> {
+    let file = MappedFile::open(file_path).map_err(|e| ObjParseError::io(file_path, e))?;
+    let (Header {format, elements, lines: header_lines}, body) = parse_header(&file, file_path)?;
+    let mut warnings = Vec::new();
+
+    let vertex_element = elements.iter().position(|element| element.name == "vertex")
+        .ok_or_else(|| malformed(file_path, "the header declares no vertex element"))?;
+    let layout = vertex_layout(&elements[vertex_element])
+        .ok_or_else(|| malformed(file_path, "the vertex element needs x, y and z properties"))?;
+    let face_list = elements.iter().find(|element| element.name == "face")
+        .and_then(|element| list_position(element, &["vertex_indices", "vertex_index"]));
+
+    let mut reader = match format {
+        Format::Ascii => Reader::Ascii(AsciiReader::new(body, header_lines)),
+        Format::BinaryLittleEndian => Reader::Binary(BinaryReader {bytes: body, big_endian: false}),
+        Format::BinaryBigEndian => Reader::Binary(BinaryReader {bytes: body, big_endian: true}),
+    };
+    let mut vertices: Vec<Vertex> = Vec::new();
+    // corners of each face with the line it comes from, checked once every vertex is read
+    let mut faces: Vec<(Vec<f64>, usize)> = Vec::new();
+    'elements: for element in &elements {
+        for _ in 0..element.count {
+            let record = match reader.read_record(element, file_path) {
+                Ok(record) => record,
+                Err((error, can_continue)) => {
+                    if !options.is_lenient() {
+                        return Err(error);
+                    }
+                    warnings.push(error);
+                    if !can_continue {
+                        break 'elements;
+                    }
+                    // a broken vertex still takes its slot so that the faces keep pointing at the right ones
+                    if element.name == "vertex" {
+                        vertices.push(build_vertex(&layout, &vec![f64::NAN; element.properties.len()], vertices.len()));
+                    }
+                    continue;
+                }
+            };
+            if element.name == "vertex" {
+                vertices.push(build_vertex(&layout, &record.scalars, vertices.len()));
+            } else if element.name == "face" {
+                if let Some(list) = face_list {
+                    faces.push((record.lists[list].clone(), record.line_number));
+                }
+            }
+        }
+    }
+
+    let mut indices: Vec<GLuint> = Vec::new();
+    for (corners, line_number) in faces {
+        match triangulate_face(&corners, &vertices) {
+            Ok(triangles) => indices.extend(triangles),
+            Err(kind) => {
+                let error = ObjParseError::new(file_path, line_number, 0, "", kind);
+                if !options.is_lenient() {
+                    return Err(error);
+                }
+                warnings.push(error);
+            }
+        }
+    }
+
+    let num_indices = indices.len();
+    let has_vertex_colors = layout.color.is_some();
+    let vertex_properties = layout.extra.iter().map(|&i| elements[vertex_element].properties[i].name.clone()).collect();
+    let mut obj_data = ObjData::new(vertices, indices, vec![1; num_indices / 3]);
+    obj_data.sub_meshes = finish_sub_meshes(vec![SubMesh::new("", "", 0)], num_indices);
+    obj_data.has_vertex_colors = has_vertex_colors;
+    obj_data.vertex_properties = vertex_properties;
+    Ok((obj_data, warnings))
+}
+
+pub fn looks_like_ply(head: &[u8]) -> bool {
+    head.starts_with(b"ply\n") || head.starts_with(b"ply\r\n")
+}
+
+// the header is text up to the end_header line, the body starts right after it. its lines are split here
+// rather than by the obj tokenizer: a backslash or a '#' means nothing special in a ply header
+fn parse_header<'a>(bytes: &'a [u8], file_path: &str) -> Result<(Header, &'a [u8]), ObjParseError> {
+    if !looks_like_ply(bytes) {
+        return Err(malformed(file_path, "a ply file starts with a 'ply' line"));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut offset = 0;
+    // the header can't be tokenized in one go since the body may be binary
+    for (index, line) in bytes.split_inclusive(|&b| b == b'\n').enumerate() {
+        offset += line.len();
+        let line_number = index + 1;
+        let line = std::str::from_utf8(line)
+            .map_err(|_| ObjParseError::new(file_path, line_number, 0, "", ObjParseErrorKind::Malformed("the header isn't text")))?;
+        let mut parts = line.split_whitespace();
+        let error = |token: &str, kind| header_error(file_path, line_number, line, token, kind);
+        match parts.next() {
+            Some("ply" | "comment" | "obj_info") | None => {}
+            Some("format") => {
+                format = match (parts.next(), parts.next()) {
+                    (Some("ascii"), Some("1.0")) => Some(Format::Ascii),
+                    (Some("binary_little_endian"), Some("1.0")) => Some(Format::BinaryLittleEndian),
+                    (Some("binary_big_endian"), Some("1.0")) => Some(Format::BinaryBigEndian),
+                    (Some(token), _) => return Err(error(token, ObjParseErrorKind::Malformed("unsupported format, expected ascii, binary_little_endian or binary_big_endian 1.0"))),
+                    (None, _) => return Err(error("", ObjParseErrorKind::MissingValue("format"))),
+                };
+            }
+            Some("element") => {
+                let name = parts.next().ok_or_else(|| error("", ObjParseErrorKind::MissingValue("element name")))?;
+                let count = parts.next().ok_or_else(|| error("", ObjParseErrorKind::MissingValue("element count")))?;
+                let count = count.parse::<usize>().map_err(|_| error(count, ObjParseErrorKind::InvalidNumber))?;
+                elements.push(Element {name: name.to_string(), count, properties: Vec::new()});
+            }
+            Some(keyword @ "property") => {
+                let element = elements.last_mut().ok_or_else(|| error(keyword, ObjParseErrorKind::Malformed("property declared before any element")))?;
+                let scalar_type = |parts: &mut std::str::SplitWhitespace| {
+                    let name = parts.next().ok_or_else(|| error("", ObjParseErrorKind::MissingValue("property type")))?;
+                    ScalarType::from_name(name).ok_or_else(|| error(name, ObjParseErrorKind::Malformed("unknown property type")))
+                };
+                let kind = match parts.clone().next() {
+                    Some("list") => {
+                        parts.next();
+                        PropertyType::List(scalar_type(&mut parts)?, scalar_type(&mut parts)?)
+                    }
+                    _ => PropertyType::Scalar(scalar_type(&mut parts)?),
+                };
+                let name = parts.next().ok_or_else(|| error("", ObjParseErrorKind::MissingValue("property name")))?;
+                element.properties.push(Property {name: name.to_string(), kind});
+            }
+            Some("end_header") => {
+                let format = format.ok_or_else(|| malformed(file_path, "the header has no format line"))?;
+                return Ok((Header {format, elements, lines: line_number}, &bytes[offset..]));
+            }
+            Some(keyword) => return Err(error(keyword, ObjParseErrorKind::UnknownStatement)),
+        }
+    }
+    Err(malformed(file_path, "the header has no end_header line"))
+}
+
+fn vertex_layout(element: &Element) -> Option<VertexLayout> {
+    let find = |names: &[&str]| -> Option<Vec<usize>> {
+        names.iter().map(|&name| scalar_position(element, name)).collect()
+    };
+    let texture_names: [[&str; 2]; 4] = [["s", "t"], ["u", "v"], ["texture_u", "texture_v"], ["texture_s", "texture_t"]];
+    let texture_coord = texture_names.iter().find_map(|names| find(names)).map(|found| [found[0], found[1]]);
+    let color = find(&["red", "green", "blue"]).map(|found| {
+        let scale = match element.properties[found[0]].kind {
+            PropertyType::Scalar(scalar_type) => scalar_type.color_scale(),
+            PropertyType::List(..) => 1.0,
+        };
+        ([found[0], found[1], found[2]], scale)
+    });
+    let position = find(&["x", "y", "z"])?;
+    let normal = find(&["nx", "ny", "nz"]).map(|found| [found[0], found[1], found[2]]);
+
+    let mut used: Vec<usize> = position.clone();
+    used.extend(normal.iter().flatten());
+    used.extend(texture_coord.iter().flatten());
+    used.extend(color.iter().flat_map(|(found, _)| found));
+    used.extend(scalar_position(element, "alpha"));
+    let extra = (0..element.properties.len())
+        .filter(|i| !used.contains(i) && matches!(element.properties[*i].kind, PropertyType::Scalar(_)))
+        .collect();
+    Some(VertexLayout {position: [position[0], position[1], position[2]], normal, texture_coord, color, extra})
+}
+
+// index of a property among the scalars of a record
+fn scalar_position(element: &Element, name: &str) -> Option<usize> {
+    element.properties.iter().position(|property| property.name == name && matches!(property.kind, PropertyType::Scalar(_)))
+}
+
+// index of a property among the lists of a record
+fn list_position(element: &Element, names: &[&str]) -> Option<usize> {
+    element.properties.iter()
+        .filter(|property| matches!(property.kind, PropertyType::List(..)))
+        .position(|property| names.contains(&property.name.as_str()))
+}
+
+// scalars are indexed like the properties, lists take a NaN placeholder there
+fn build_vertex(layout: &VertexLayout, scalars: &[f64], index: usize) -> Vertex {
+    let vec3 = |[x, y, z]: [usize; 3]| Vec3::new(scalars[x] as f32, scalars[y] as f32, scalars[z] as f32);
+    let position = vec3(layout.position);
+    // same fallbacks as obj files: a zero normal generated later, planar texture coordinates and shades of gray
+    let normal = layout.normal.map_or(Vec3::new(0.0, 0.0, 0.0), vec3);
+    let (text_x, text_y) = layout.texture_coord.map_or((position.z, position.y), |[s, t]| (scalars[s] as f32, scalars[t] as f32));
+    let rgb = match layout.color {
+        Some(([r, g, b], scale)) => Vec3::new((scalars[r] / scale) as f32, (scalars[g] / scale) as f32, (scalars[b] / scale) as f32),
+        None => generate_random_color(index as u32),
+    };
+    let properties = layout.extra.iter().map(|&i| scalars[i] as f32).collect();
+    Vertex {position, rgb, text_x, text_y, normal, properties}
+}
+
+fn triangulate_face(corners: &[f64], vertices: &[Vertex]) -> Result<Vec<GLuint>, ObjParseErrorKind> {
+    if corners.len() < 3 {
+        return Err(ObjParseErrorKind::NotEnoughFaceVertices);
+    }
+    let indices: Vec<GLuint> = corners.iter()
+        .map(|&corner| {
+            let in_range = corner >= 0.0 && corner.fract() == 0.0 && (corner as usize) < vertices.len();
+            if in_range { Ok(corner as GLuint) } else { Err(ObjParseErrorKind::IndexOutOfRange("vertex")) }
+        })
+        .collect::<Result<_, _>>()?;
+    if indices.len() == 3 {
+        return Ok(indices);
+    }
+    let points: Vec<Vec3> = indices.iter().map(|&index| vertices[index as usize].position).collect();
+    Ok(triangulation::triangulate(&points).into_iter().flatten().map(|corner| indices[corner]).collect())
+}
+
+enum Reader<'a> {
+    Ascii(AsciiReader<'a>),
+    Binary(BinaryReader<'a>),
+}
+
+impl<'a> Reader<'a> {
+    // the error comes with whether the following records can still be read
+    fn read_record(&mut self, element: &Element, file_path: &str) -> Result<Record, (ObjParseError, bool)> {
+        match self {
+            Reader::Ascii(reader) => reader.read_record(element, file_path),
+            Reader::Binary(reader) => reader.read_record(element)
+                .ok_or_else(|| (malformed(file_path, "the data ends before every element is read"), false)),
+        }
+    }
+}
+
+// one record per line, as every ply writer does
+struct AsciiReader<'a> {
+    statements: Statements<'a>,
+    header_lines: usize,
+}
+
+impl<'a> AsciiReader<'a> {
+    fn new(body: &'a [u8], header_lines: usize) -> Self {
+        AsciiReader {statements: Statements::new(body), header_lines}
+    }
+
+    fn read_record(&mut self, element: &Element, file_path: &str) -> Result<Record, (ObjParseError, bool)> {
+        let mut statement = loop {
+            match self.statements.next() {
+                Some(statement) if statement.tokens().next().is_none() => continue,
+                Some(statement) => break statement,
+                None => return Err((malformed(file_path, "the data ends before every element is read"), false)),
+            }
+        };
+        statement.shift_lines(self.header_lines);
+        let mut parts = statement.tokens();
+        let mut next_value = || -> Result<f64, ObjParseError> {
+            let token = parts.next().ok_or_else(|| located_error(file_path, &statement, "", ObjParseErrorKind::MissingValue("property value")))?;
+            token.parse::<f64>().map_err(|_| located_error(file_path, &statement, token, ObjParseErrorKind::InvalidNumber))
+        };
+        let line_number = statement.line_number();
+        let mut record = Record {scalars: Vec::with_capacity(element.properties.len()), lists: Vec::new(), line_number};
+        for property in &element.properties {
+            match property.kind {
+                PropertyType::Scalar(_) => record.scalars.push(next_value().map_err(|error| (error, true))?),
+                PropertyType::List(..) => {
+                    let count = next_value().map_err(|error| (error, true))?;
+                    let list = (0..count.max(0.0) as usize).map(|_| next_value()).collect::<Result<Vec<f64>, _>>()
+                        .map_err(|error| (error, true))?;
+                    record.scalars.push(f64::NAN);
+                    record.lists.push(list);
+                }
+            }
+        }
+        Ok(record)
+    }
+}
+
+struct BinaryReader<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> BinaryReader<'a> {
+    // none once the data runs out
+    fn read_record(&mut self, element: &Element) -> Option<Record> {
+        let mut record = Record {scalars: Vec::with_capacity(element.properties.len()), lists: Vec::new(), line_number: 0};
+        for property in &element.properties {
+            match property.kind {
+                PropertyType::Scalar(scalar_type) => record.scalars.push(self.read_scalar(scalar_type)?),
+                PropertyType::List(count_type, item_type) => {
+                    let count = self.read_scalar(count_type)?;
+                    let list = (0..count.max(0.0) as usize).map(|_| self.read_scalar(item_type)).collect::<Option<Vec<f64>>>()?;
+                    record.scalars.push(f64::NAN);
+                    record.lists.push(list);
+                }
+            }
+        }
+        Some(record)
+    }
+
+    fn read_scalar(&mut self, scalar_type: ScalarType) -> Option<f64> {
+        if self.bytes.len() < scalar_type.size() {
+            return None;
+        }
+        let (value, rest) = self.bytes.split_at(scalar_type.size());
+        self.bytes = rest;
+        let mut buffer = [0u8; 8];
+        buffer[..value.len()].copy_from_slice(value);
+        if self.big_endian {
+            buffer[..value.len()].reverse();
+        }
+        let [b0, b1, b2, b3, ..] = buffer;
+        Some(match scalar_type {
+            ScalarType::Int8 => b0 as i8 as f64,
+            ScalarType::UInt8 => b0 as f64,
+            ScalarType::Int16 => i16::from_le_bytes([b0, b1]) as f64,
+            ScalarType::UInt16 => u16::from_le_bytes([b0, b1]) as f64,
+            ScalarType::Int32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::UInt32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::Float32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::Float64 => f64::from_le_bytes(buffer),
+        })
+    }
+}
+
+fn malformed(file_path: &str, reason: &'static str) -> ObjParseError {
+    ObjParseError::new(file_path, 0, 0, "", ObjParseErrorKind::Malformed(reason))
+}
+
+fn located_error(file_path: &str, statement: &Statement, token: &str, kind: ObjParseErrorKind) -> ObjParseError {
+    let (line_number, column) = statement.locate(token);
+    ObjParseError::new(file_path, line_number, column, token, kind)
+}
+
+// a missing token is reported at the end of the line
+fn header_error(file_path: &str, line_number: usize, line: &str, token: &str, kind: ObjParseErrorKind) -> ObjParseError {
+    let offset = match token.is_empty() {
+        true => line.trim_end().len(),
+        false => token.as_ptr() as usize - line.as_ptr() as usize,
+    };
+    ObjParseError::new(file_path, line_number, line[..offset].chars().count() + 1, token, kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{write_test_file, LENIENT, STRICT};
+
+    const BINARY_HEADER: &str = "ply\nformat {} 1.0\ncomment a backslash ends this comment \\\n\
+        element vertex 4\nproperty float x\nproperty float y\nproperty float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\nproperty float confidence\n\
+        element edge 1\nproperty int vertex1\nproperty int vertex2\n\
+        element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    // a quad of coloured vertices, an edge element to skip, then the face
+    fn binary_ply(format: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes = BINARY_HEADER.replace("{}", format).into_bytes();
+        let mut put = |value: &[u8]| {
+            let mut value = value.to_vec();
+            if big_endian {
+                value.reverse();
+            }
+            bytes.extend(value);
+        };
+        for (i, [x, y]) in [[0.0f32, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]].into_iter().enumerate() {
+            [x, y, 0.0].iter().for_each(|value| put(&value.to_le_bytes()));
+            put(&[255]);
+            put(&[0]);
+            put(&[51 * i as u8]);
+            put(&(i as f32 / 4.0).to_le_bytes());
+        }
+        [0i32, 1].iter().for_each(|value| put(&value.to_le_bytes()));
+        put(&[4]);
+        [0i32, 1, 2, 3].iter().for_each(|value| put(&value.to_le_bytes()));
+        bytes
+    }
+
+    #[test]
+    fn both_byte_orders_read_the_same() {
+        for (format, big_endian) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+            let path = write_test_file("binary.ply", &binary_ply(format, big_endian));
+            let (obj_data, warnings) = parse_ply_file(&path, &STRICT).unwrap();
+            assert!(warnings.is_empty(), "{}", format);
+            assert_eq!(obj_data.num_indices, 6);
+            let vertex = &obj_data.vertices[2];
+            assert_eq!((vertex.position.x, vertex.position.y), (1.0, 1.0));
+            assert_eq!((vertex.rgb.x, vertex.rgb.y, vertex.rgb.z), (1.0, 0.0, 0.4));
+            assert_eq!(vertex.properties, [0.5]);
+            assert_eq!(obj_data.vertex_properties, ["confidence"]);
+            assert!(obj_data.has_vertex_colors);
+        }
+    }
+
+    #[test]
+    fn list_counts_past_the_end_of_the_data() {
+        // the face claims 255 corners, then 2^32 - 1 with a uint count
+        let mut bytes = binary_ply("binary_little_endian", false);
+        let count = bytes.len() - 4 * 4 - 1;
+        bytes[count] = 255;
+        let path = write_test_file("binary-list.ply", &bytes);
+        let error = parse_ply_file(&path, &STRICT).err().unwrap();
+        assert_eq!(error.kind, ObjParseErrorKind::Malformed("the data ends before every element is read"));
+
+        let header = BINARY_HEADER.replace("{}", "binary_little_endian");
+        let mut bytes = header.replace("list uchar", "list uint").into_bytes();
+        bytes.extend(&binary_ply("binary_little_endian", false)[header.len()..count]);
+        bytes.extend(u32::MAX.to_le_bytes());
+        bytes.extend([0; 12]);
+        let path = write_test_file("binary-uint-list.ply", &bytes);
+        // the vertices are kept, the face that can't be read is dropped
+        let (obj_data, warnings) = parse_ply_file(&path, &LENIENT).unwrap();
+        assert_eq!((obj_data.vertices.len(), obj_data.num_indices, warnings.len()), (4, 0, 1));
+        assert_eq!((obj_data.vertices[3].position.y, obj_data.vertices[3].properties[0]), (1.0, 0.75));
+    }
+
+    const ASCII: &str = "ply\nformat ascii 1.0\nelement vertex 3\nproperty double x\nproperty double y\nproperty double z\n\
+        element face 3\nproperty list uchar uint vertex_index\nend_header\n\
+        0 0 0\n1 0 0\n0 x 0\n3 0 1 2\n3 0 1 3\n1e300 0 1\n";
+
+    #[test]
+    fn ascii_records_are_located() {
+        let path = write_test_file("ascii.ply", ASCII.as_bytes());
+        let error = parse_ply_file(&path, &STRICT).err().unwrap();
+        assert_eq!((error.line_number, error.column, error.kind), (12, 3, ObjParseErrorKind::InvalidNumber));
+
+        let (obj_data, warnings) = parse_ply_file(&path, &LENIENT).unwrap();
+        let warnings: Vec<_> = warnings.into_iter().map(|warning| (warning.line_number, warning.kind)).collect();
+        // a huge list count runs out of values on its line, the out of range face is found once every vertex is read
+        assert_eq!(warnings, [
+            (12, ObjParseErrorKind::InvalidNumber),
+            (15, ObjParseErrorKind::MissingValue("property value")),
+            (14, ObjParseErrorKind::IndexOutOfRange("vertex")),
+        ]);
+        // the broken vertex keeps its slot, its NaN position is left to the mesh validation
+        assert_eq!((obj_data.vertices.len(), obj_data.num_indices), (3, 3));
+        assert!(obj_data.vertices[2].position.y.is_nan());
+    }
+
+    #[test]
+    fn header_lines_are_not_obj_statements() {
+        // a trailing backslash doesn't continue a header line, and a '#' isn't a comment
+        let header = "ply\nformat ascii 1.0\ncomment \\\nelement vertex 3\nproperty float x#1\nproperty float y\nproperty float z\nproperty float x\nend_header\n";
+        let path = write_test_file("header.ply", format!("{}0 0 0 7\n1 0 0 8\n0 1 0 9\n", header).as_bytes());
+        let (obj_data, warnings) = parse_ply_file(&path, &STRICT).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(obj_data.vertex_properties, ["x#1"]);
+        assert_eq!(obj_data.vertices[1].position.x, 8.0);
+
+        let path = write_test_file("header-missing.ply", b"ply\nformat ascii 1.0\nelement vertex 3\nproperty float\n");
+        let error = parse_ply_file(&path, &STRICT).err().unwrap();
+        assert_eq!((error.line_number, error.column, error.kind), (4, 15, ObjParseErrorKind::MissingValue("property name")));
+    }
+}
@@ -1,8 +1,11 @@
 use crate::glfw::{Context, Key, Action};
 use std::sync::mpsc::Receiver;
 use std::collections::HashSet;
+use std::os::raw::c_void;
+use gl::types::GLuint;
 
 use crate::models::obj_data:: ObjData;
+use crate::models::material::{Material, MaterialRange};
 use crate::models::gl_var::GlVar;
 use crate::models::mat4::Mat4;
 use crate::models::vec3::Vec3;
@@ -39,30 +42,122 @@ pub fn render_loop(glvar: &mut GlVar, vao: &u32, obj_data: &mut ObjData) {
             gl::ClearColor(0.4, 0.2, 0.7 , 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
             
-            let display_texture_location = gl::GetUniformLocation(glvar.shader_prgm_id, "displayTexture\0".as_ptr() as *const i8);
+            // glUniform sets the uniforms of the program in use
+            gl::UseProgram(glvar.shader_prgm_id);
+            let display_texture_location = gl::GetUniformLocation(glvar.shader_prgm_id, c"displayTexture".as_ptr());
             if obj_data.display_texture {
                 gl::Uniform1i(display_texture_location, 1);
             } else {
                 gl::Uniform1i(display_texture_location, 0);
             }
-            gl::BindTexture(gl::TEXTURE_2D, glvar.texture_id);
-            gl::UseProgram(glvar.shader_prgm_id);
+            let vertex_colors_location = gl::GetUniformLocation(glvar.shader_prgm_id, c"vertexColors".as_ptr());
+            gl::Uniform1i(vertex_colors_location, obj_data.has_vertex_colors as i32);
 
-            let model_location = gl::GetUniformLocation(glvar.shader_prgm_id, "model\0".as_ptr() as *const i8);
-            gl::UniformMatrix4fv(model_location, 1, gl::FALSE, model.as_ptr());
-            let view_location = gl::GetUniformLocation(glvar.shader_prgm_id, "view\0".as_ptr() as *const i8);
+            let view_location = gl::GetUniformLocation(glvar.shader_prgm_id, c"view".as_ptr());
             gl::UniformMatrix4fv(view_location, 1, gl::FALSE, view.as_ptr());
-            let projection_location = gl::GetUniformLocation(glvar.shader_prgm_id, "projection\0".as_ptr() as *const i8);
+            let projection_location = gl::GetUniformLocation(glvar.shader_prgm_id, c"projection".as_ptr());
             gl::UniformMatrix4fv(projection_location, 1, gl::FALSE,projection.as_ptr());
 
             gl::BindVertexArray(*vao);
-            gl::DrawElements(gl::TRIANGLES, obj_data.num_indices as i32, gl::UNSIGNED_SHORT, std::ptr::null());
+            if obj_data.sub_meshes.is_empty() {
+                set_sub_mesh_uniforms(glvar.shader_prgm_id, &model, false);
+                draw_indices(glvar.shader_prgm_id, glvar.texture_id, obj_data, 0, obj_data.num_indices);
+            }
+            for sub_mesh in &obj_data.sub_meshes {
+                if !sub_mesh.visible {
+                    continue;
+                }
+                let sub_mesh_model = Mat4::identity().translate(sub_mesh.offset.x, sub_mesh.offset.y, sub_mesh.offset.z) * model;
+                set_sub_mesh_uniforms(glvar.shader_prgm_id, &sub_mesh_model, sub_mesh.highlighted);
+                draw_indices(glvar.shader_prgm_id, glvar.texture_id, obj_data, sub_mesh.first_index, sub_mesh.num_indices);
+            }
+            draw_lines_and_points(glvar.shader_prgm_id, obj_data, &model);
         }
         glvar.window.swap_buffers();
         glvar.glfw.poll_events();
     }
 }
 
+// lines and points are stored after the triangles in the index buffer, they belong to no sub-mesh and have no material
+unsafe fn draw_lines_and_points(shader_prgm_id: GLuint, obj_data: &ObjData, model: &Mat4) {
+    if obj_data.line_indices.is_empty() && obj_data.point_indices.is_empty() {
+        return;
+    }
+    set_sub_mesh_uniforms(shader_prgm_id, model, false);
+    set_material_uniforms(shader_prgm_id, None);
+    let element_size = obj_data.indices.element_size();
+    if !obj_data.line_indices.is_empty() {
+        // core profile drivers are only required to support a width of 1
+        gl::LineWidth(obj_data.line_width);
+        let offset = obj_data.num_indices * element_size;
+        gl::DrawElements(gl::LINES, obj_data.line_indices.len() as i32, obj_data.indices.gl_type(), offset as *const c_void);
+    }
+    if !obj_data.point_indices.is_empty() {
+        gl::PointSize(obj_data.point_size);
+        let offset = (obj_data.num_indices + obj_data.line_indices.len()) * element_size;
+        gl::DrawElements(gl::POINTS, obj_data.point_indices.len() as i32, obj_data.indices.gl_type(), offset as *const c_void);
+    }
+}
+
+unsafe fn set_sub_mesh_uniforms(shader_prgm_id: GLuint, model: &Mat4, highlighted: bool) {
+    let model_location = gl::GetUniformLocation(shader_prgm_id, c"model".as_ptr());
+    gl::UniformMatrix4fv(model_location, 1, gl::FALSE, model.as_ptr());
+    let highlight_location = gl::GetUniformLocation(shader_prgm_id, c"highlight".as_ptr());
+    gl::Uniform1i(highlight_location, highlighted as i32);
+}
+
+// draws the indices in [first_index, first_index + num_indices), split where the material changes
+unsafe fn draw_indices(shader_prgm_id: GLuint, default_texture_id: GLuint, obj_data: &ObjData, first_index: usize, num_indices: usize) {
+    let end = first_index + num_indices;
+    if obj_data.material_ranges.is_empty() {
+        let range = MaterialRange {material: None, first_index, num_indices};
+        draw_range(shader_prgm_id, default_texture_id, obj_data, &range);
+    }
+    for range in &obj_data.material_ranges {
+        let start = range.first_index.max(first_index);
+        let stop = (range.first_index + range.num_indices).min(end);
+        if start < stop {
+            let overlap = MaterialRange {material: range.material, first_index: start, num_indices: stop - start};
+            draw_range(shader_prgm_id, default_texture_id, obj_data, &overlap);
+        }
+    }
+}
+
+unsafe fn draw_range(shader_prgm_id: GLuint, default_texture_id: GLuint, obj_data: &ObjData, range: &MaterialRange) {
+    let material = range.material.map(|i| &obj_data.materials[i]);
+    set_material_uniforms(shader_prgm_id, material);
+    let texture_id = material.and_then(|material| material.diffuse_texture_id).unwrap_or(default_texture_id);
+    gl::BindTexture(gl::TEXTURE_2D, texture_id);
+    let offset = range.first_index * obj_data.indices.element_size();
+    gl::DrawElements(gl::TRIANGLES, range.num_indices as i32, obj_data.indices.gl_type(), offset as *const c_void);
+}
+
+unsafe fn set_material_uniforms(shader_prgm_id: GLuint, material: Option<&Material>) {
+    let has_material_location = gl::GetUniformLocation(shader_prgm_id, c"hasMaterial".as_ptr());
+    let material = match material {
+        Some(material) => material,
+        None => {
+            gl::Uniform1i(has_material_location, 0);
+            return;
+        }
+    };
+    gl::Uniform1i(has_material_location, 1);
+    let ambient_location = gl::GetUniformLocation(shader_prgm_id, c"ambientColor".as_ptr());
+    gl::Uniform3f(ambient_location, material.ambient.x, material.ambient.y, material.ambient.z);
+    let diffuse_location = gl::GetUniformLocation(shader_prgm_id, c"diffuseColor".as_ptr());
+    gl::Uniform3f(diffuse_location, material.diffuse.x, material.diffuse.y, material.diffuse.z);
+    let specular_location = gl::GetUniformLocation(shader_prgm_id, c"specularColor".as_ptr());
+    gl::Uniform3f(specular_location, material.specular.x, material.specular.y, material.specular.z);
+    let emissive_location = gl::GetUniformLocation(shader_prgm_id, c"emissiveColor".as_ptr());
+    gl::Uniform3f(emissive_location, material.emissive.x, material.emissive.y, material.emissive.z);
+    let shininess_location = gl::GetUniformLocation(shader_prgm_id, c"shininess".as_ptr());
+    gl::Uniform1f(shininess_location, material.shininess);
+    let opacity_location = gl::GetUniformLocation(shader_prgm_id, c"opacity".as_ptr());
+    gl::Uniform1f(opacity_location, material.dissolve);
+    let illum_location = gl::GetUniformLocation(shader_prgm_id, c"illum".as_ptr());
+    gl::Uniform1i(illum_location, material.illum as i32);
+}
+
 fn process_events(window: &mut glfw::Window, events: &Receiver<(f64, glfw::WindowEvent)>, keys: &mut HashSet<Key>, obj_data: &mut ObjData) {
     for (_, event) in glfw::flush_messages(events) {
         match event {
@@ -73,6 +168,12 @@ fn process_events(window: &mut glfw::Window, events: &Receiver<(f64, glfw::Windo
                 if key == Key::T {
                     obj_data.display_texture = !obj_data.display_texture;
                 }
+                else if key == Key::Tab {
+                    select_next_sub_mesh(obj_data);
+                }
+                else if key == Key::H {
+                    toggle_selected_sub_mesh_visibility(obj_data);
+                }
                 else if key == Key::Escape {
                     window.set_should_close(true);
                 }
@@ -102,23 +203,64 @@ fn process_events(window: &mut glfw::Window, events: &Receiver<(f64, glfw::Windo
     if keys.contains(&Key::X) {
         obj_data.orientation_z -= TRANSFORM_SPEED;
     }
+    let mut translation = Vec3::new(0.0, 0.0, 0.0);
     if keys.contains(&Key::D) {
-        obj_data.position_x -= TRANSFORM_SPEED;
+        translation.x -= TRANSFORM_SPEED;
     }
     if keys.contains(&Key::A) {
-        obj_data.position_x += TRANSFORM_SPEED;
+        translation.x += TRANSFORM_SPEED;
     }
     if keys.contains(&Key::W) {
-        obj_data.position_y += TRANSFORM_SPEED;
+        translation.y += TRANSFORM_SPEED;
     }
     if keys.contains(&Key::S) {
-        obj_data.position_y -= TRANSFORM_SPEED;
+        translation.y -= TRANSFORM_SPEED;
     }
     if keys.contains(&Key::Q) {
-        obj_data.position_z += TRANSFORM_SPEED;
+        translation.z += TRANSFORM_SPEED;
     }
     if keys.contains(&Key::E) {
-        obj_data.position_z -= TRANSFORM_SPEED;
+        translation.z -= TRANSFORM_SPEED;
+    }
+    // the movement keys move the selected part, or the whole object when nothing is selected
+    match obj_data.selected_sub_mesh {
+        Some(i) => obj_data.sub_meshes[i].offset = obj_data.sub_meshes[i].offset.add(translation),
+        None => {
+            obj_data.position_x += translation.x;
+            obj_data.position_y += translation.y;
+            obj_data.position_z += translation.z;
+        }
+    }
+}
+
+// cycles through the parts then back to the whole object, the selected part is highlighted
+fn select_next_sub_mesh(obj_data: &mut ObjData) {
+    if let Some(i) = obj_data.selected_sub_mesh {
+        obj_data.sub_meshes[i].highlighted = false;
+    }
+    obj_data.selected_sub_mesh = match obj_data.selected_sub_mesh {
+        None if !obj_data.sub_meshes.is_empty() => Some(0),
+        Some(i) if i + 1 < obj_data.sub_meshes.len() => Some(i + 1),
+        _ => None,
+    };
+    match obj_data.selected_sub_mesh {
+        Some(i) => {
+            obj_data.sub_meshes[i].highlighted = true;
+            println!("Selected part: {}", obj_data.sub_meshes[i].name());
+        }
+        None => println!("Selected part: none"),
+    }
+}
+
+// with nothing selected, every hidden part is shown again
+fn toggle_selected_sub_mesh_visibility(obj_data: &mut ObjData) {
+    match obj_data.selected_sub_mesh {
+        Some(i) => obj_data.sub_meshes[i].visible = !obj_data.sub_meshes[i].visible,
+        None => {
+            for sub_mesh in obj_data.sub_meshes.iter_mut() {
+                sub_mesh.visible = true;
+            }
+        }
     }
 }
 
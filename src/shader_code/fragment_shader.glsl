@@ -2,19 +2,66 @@
 out vec4 FragColor;
 
 flat in vec3 ourColor;
+in vec3 vertexColor;
 in vec2 TexCoord;
+in vec3 Normal;
 
 // texture sampler
 uniform sampler2D texture1;
 
 uniform bool displayTexture;
+// colours read from the file are interpolated, generated ones stay flat per face
+uniform bool vertexColors;
+uniform bool highlight;
+
+// mtl material of the range being drawn
+uniform bool hasMaterial;
+uniform vec3 ambientColor;
+uniform vec3 diffuseColor;
+uniform vec3 specularColor;
+uniform vec3 emissiveColor;
+uniform float shininess;
+uniform float opacity;
+uniform int illum;
+
+const vec3 lightDirection = normalize(vec3(0.3, 0.5, 1.0));
+const vec3 viewDirection = vec3(0.0, 0.0, 1.0);
+
+vec3 shadeMaterial()
+{
+	// illum 0: color on and ambient off
+	if (illum == 0) {
+		return diffuseColor;
+	}
+	vec3 normal = normalize(Normal);
+	float diffuse = max(dot(normal, lightDirection), 0.0);
+	vec3 color = emissiveColor + ambientColor + diffuseColor * (0.2 + 0.8 * diffuse);
+	// illum 1 has no highlights
+	if (illum >= 2 && shininess > 0.0) {
+		vec3 halfway = normalize(lightDirection + viewDirection);
+		color += specularColor * pow(max(dot(normal, halfway), 0.0), shininess);
+	}
+	return color;
+}
 
 void main()
 {
 	if (displayTexture) {
 		FragColor = texture(texture1, TexCoord);
+		if (hasMaterial) {
+			FragColor.a *= opacity;
+		}
+	}
+	else if (hasMaterial) {
+		FragColor = vec4(shadeMaterial(), opacity);
+	}
+	else if (vertexColors) {
+		FragColor = vec4(vertexColor, 1.0);
 	}
 	else {
 		FragColor = vec4(ourColor, 1.0);
 	}
+	if (highlight) {
+		FragColor.rgb = mix(FragColor.rgb, vec3(1.0, 0.8, 0.2), 0.5);
+	}
 }
\ No newline at end of file
@@ -2,9 +2,12 @@
 layout (location = 0) in vec3 aPos;
 layout (location = 1) in vec3 aColor;
 layout (location = 2) in vec2 aTexCoord;
+layout (location = 3) in vec3 aNormal;
 
 flat out vec3 ourColor;
+out vec3 vertexColor;
 out vec2 TexCoord;
+out vec3 Normal;
 
 uniform mat4 model;
 uniform mat4 view;
@@ -14,5 +17,7 @@ void main()
 {
 	gl_Position = projection * view * model * vec4(aPos, 1.0);
 	ourColor = aColor;
+	vertexColor = aColor;
 	TexCoord = aTexCoord;
+	Normal = mat3(model) * aNormal;
 }
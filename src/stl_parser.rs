@@ -0,0 +1,312 @@
+use std::collections::HashSet;
+use std::str::SplitWhitespace;
+
+use gl::types::GLuint;
+
+use crate::mapped_file::MappedFile;
+use crate::models::obj_data::{ObjData, Vertex};
+use crate::models::obj_parse_error::{ObjParseError, ObjParseErrorKind};
+use crate::models::parse_options::ParseOptions;
+use crate::models::sub_mesh::SubMesh;
+use crate::models::vec3::Vec3;
+use crate::obj_parser::{finish_sub_meshes, generate_random_color};
+use crate::obj_tokenizer::{Statement, Statements};
+
+// 80 byte header then the number of triangles
+const BINARY_HEADER_SIZE: usize = 84;
+// normal, three corners and a 16 bit attribute
+const BINARY_TRIANGLE_SIZE: usize = 50;
+
+// a triangle of the file with the normal it was given, which is often left at zero
+struct Facet {
+    normal: Vec3,
+    corners: Vec<Vec3>,
+}
+
+// reads ascii (solid / facet / vertex) and binary stl files. every facet gets its own three vertices
+// carrying its normal, so the part is flat shaded like a mechanical model should be; --weld joins
+// the corners of coplanar facets. each ascii solid becomes a sub-mesh named after it
+pub fn parse_stl_file(file_path: &str, options: &ParseOptions) -> Result<(ObjData, Vec<ObjParseError>), ObjParseError> {
+    let file = MappedFile::open(file_path).map_err(|e| ObjParseError::io(file_path, e))?;
+    let mut warnings = Vec::new();
+
+    let (facets, sub_meshes) = if is_binary(&file) {
+        (parse_binary(&file, file_path, options, &mut warnings)?, vec![SubMesh::new("", "", 0)])
+    } else {
+        parse_ascii(&file, file_path, options, &mut warnings)?
+    };
+
+    let mut vertices = Vec::with_capacity(facets.len() * 3);
+    let mut indices: Vec<GLuint> = Vec::with_capacity(facets.len() * 3);
+    let mut smoothing_groups = Vec::with_capacity(facets.len());
+    for (i, facet) in facets.iter().enumerate() {
+        // a zero or broken normal is generated again from the winding
+        let normal = if facet.normal.length().is_normal() { facet.normal.normalize() } else { Vec3::new(0.0, 0.0, 0.0) };
+        let rgb = generate_random_color(i as u32);
+        for &position in &facet.corners {
+            indices.push(vertices.len() as GLuint);
+            // same planar (z, y) projection as obj files without texture coordinates
+            vertices.push(Vertex {position, rgb, text_x: position.z, text_y: position.y, normal, properties: Vec::new()});
+        }
+        // smoothing group 0 so that generated normals stay flat
+        smoothing_groups.push(0);
+    }
+
+    let num_indices = indices.len();
+    let mut obj_data = ObjData::new(vertices, indices, smoothing_groups);
+    obj_data.sub_meshes = finish_sub_meshes(sub_meshes, num_indices);
+    Ok((obj_data, warnings))
+}
+
+// a binary file has exactly the size announced by its triangle count. ascii files start with "solid",
+// but some binary exporters also write "solid" at the start of the header, so the size is checked first
+fn is_binary(bytes: &[u8]) -> bool {
+    if let Some(count) = binary_triangle_count(bytes) {
+        if BINARY_HEADER_SIZE + count * BINARY_TRIANGLE_SIZE == bytes.len() {
+            return true;
+        }
+    }
+    !String::from_utf8_lossy(&bytes[..bytes.len().min(BINARY_HEADER_SIZE)]).trim_start().starts_with("solid")
+}
+
+// a binary file with the size announced by its header, or an ascii one
+pub fn looks_like_stl(head: &[u8], file_size: u64) -> bool {
+    let binary_size = binary_triangle_count(head).map(|count| (BINARY_HEADER_SIZE + count * BINARY_TRIANGLE_SIZE) as u64);
+    binary_size == Some(file_size) || String::from_utf8_lossy(head).trim_start().starts_with("solid")
+}
+
+fn binary_triangle_count(bytes: &[u8]) -> Option<usize> {
+    let count = bytes.get(BINARY_HEADER_SIZE - 4..BINARY_HEADER_SIZE)?;
+    Some(u32::from_le_bytes(count.try_into().unwrap()) as usize)
+}
+
+fn parse_binary(bytes: &[u8], file_path: &str, options: &ParseOptions, warnings: &mut Vec<ObjParseError>) -> Result<Vec<Facet>, ObjParseError> {
+    let malformed = |reason| ObjParseError::new(file_path, 0, 0, "", ObjParseErrorKind::Malformed(reason));
+    let count = binary_triangle_count(bytes).ok_or_else(|| malformed("too short for a binary stl header"))?;
+    let available = (bytes.len() - BINARY_HEADER_SIZE) / BINARY_TRIANGLE_SIZE;
+    if available != count || bytes.len() != BINARY_HEADER_SIZE + available * BINARY_TRIANGLE_SIZE {
+        // the triangles that are there are kept in lenient mode
+        let error = malformed("the size of the binary stl doesn't match its triangle count");
+        if !options.is_lenient() {
+            return Err(error);
+        }
+        warnings.push(error);
+    }
+
+    let data = &bytes[BINARY_HEADER_SIZE..];
+    let facets = data.chunks_exact(BINARY_TRIANGLE_SIZE)
+        .take(count)
+        .map(|triangle| {
+            let float = |i: usize| f32::from_le_bytes(triangle[i * 4..i * 4 + 4].try_into().unwrap());
+            let vec3 = |i: usize| Vec3::new(float(i), float(i + 1), float(i + 2));
+            Facet {normal: vec3(0), corners: vec![vec3(3), vec3(6), vec3(9)]}
+        })
+        .collect();
+    Ok(facets)
+}
+
+fn parse_ascii(bytes: &[u8], file_path: &str, options: &ParseOptions, warnings: &mut Vec<ObjParseError>) -> Result<(Vec<Facet>, Vec<SubMesh>), ObjParseError> {
+    let mut facets: Vec<Facet> = Vec::new();
+    let mut sub_meshes = vec![SubMesh::new("", "", 0)];
+    let mut facet: Option<Facet> = None;
+    // after an error the rest of the facet is skipped, up to its endfacet
+    let mut skipping_facet = false;
+    let mut unknown_statements: HashSet<String> = HashSet::new();
+    let mut last_line = 0;
+
+    for statement in Statements::new(bytes) {
+        last_line = statement.line_number();
+        let mut parts = statement.tokens();
+        let keyword = parts.next();
+        if skipping_facet {
+            skipping_facet = keyword != Some("endfacet");
+            continue;
+        }
+
+        let result = match keyword {
+            Some("solid") => {
+                let name = parts.collect::<Vec<&str>>().join(" ");
+                sub_meshes.push(SubMesh::new(&name, "", facets.len() * 3));
+                Ok(())
+            }
+            Some(keyword @ "facet") => start_facet(&mut facet, keyword, &mut parts),
+            Some(keyword @ "outer") => match parts.next() {
+                _ if facet.is_none() => Err((keyword, ObjParseErrorKind::Malformed("'outer loop' outside of a facet"))),
+                Some("loop") => Ok(()),
+                Some(token) => Err((token, ObjParseErrorKind::UnexpectedValue("expected 'outer loop'"))),
+                None => Err(("", ObjParseErrorKind::MissingValue("'loop'"))),
+            },
+            Some(keyword @ "vertex") => add_corner(&mut facet, keyword, &mut parts),
+            Some("endloop") => Ok(()),
+            Some(keyword @ "endfacet") => finish_facet(&mut facet, keyword, &mut facets),
+            Some("endsolid") => Ok(()),
+            Some(keyword) if unknown_statements.contains(keyword) => Ok(()),
+            Some(keyword) => {
+                unknown_statements.insert(keyword.to_string());
+                Err((keyword, ObjParseErrorKind::UnknownStatement))
+            }
+            None => Ok(()),
+        };
+
+        if let Err((token, kind)) = result {
+            let error = located_error(file_path, &statement, token, kind);
+            if !options.is_lenient() {
+                return Err(error);
+            }
+            warnings.push(error);
+            // a broken facet is dropped, along with what is left of it
+            skipping_facet = match keyword {
+                Some("facet") => {
+                    facet = None;
+                    true
+                }
+                Some("outer" | "vertex") => facet.take().is_some(),
+                _ => false,
+            };
+        }
+    }
+    if facet.is_some() {
+        let error = ObjParseError::new(file_path, last_line, 0, "", ObjParseErrorKind::Malformed("the last facet has no endfacet"));
+        if !options.is_lenient() {
+            return Err(error);
+        }
+        warnings.push(error);
+    }
+    Ok((facets, sub_meshes))
+}
+
+fn start_facet<'a>(facet: &mut Option<Facet>, keyword: &'a str, parts: &mut SplitWhitespace<'a>) -> Result<(), (&'a str, ObjParseErrorKind)> {
+    if facet.is_some() {
+        return Err((keyword, ObjParseErrorKind::Malformed("a facet starts before the previous one has ended")));
+    }
+    match parts.next() {
+        Some("normal") => {}
+        Some(token) => return Err((token, ObjParseErrorKind::UnexpectedValue("expected 'facet normal'"))),
+        None => return Err(("", ObjParseErrorKind::MissingValue("'normal'"))),
+    }
+    let normal = parse_vec3(parts, "facet normal")?;
+    *facet = Some(Facet {normal, corners: Vec::with_capacity(3)});
+    Ok(())
+}
+
+fn add_corner<'a>(facet: &mut Option<Facet>, keyword: &'a str, parts: &mut SplitWhitespace<'a>) -> Result<(), (&'a str, ObjParseErrorKind)> {
+    let position = parse_vec3(parts, "vertex")?;
+    match facet {
+        Some(facet) => facet.corners.push(position),
+        None => return Err((keyword, ObjParseErrorKind::Malformed("vertex outside of a facet"))),
+    }
+    Ok(())
+}
+
+// facets are triangles, bigger ones written by some tools are split as a fan
+fn finish_facet<'a>(facet: &mut Option<Facet>, keyword: &'a str, facets: &mut Vec<Facet>) -> Result<(), (&'a str, ObjParseErrorKind)> {
+    let Some(Facet {normal, corners}) = facet.take() else {
+        return Err((keyword, ObjParseErrorKind::Malformed("endfacet without a facet")));
+    };
+    if corners.len() < 3 {
+        return Err((keyword, ObjParseErrorKind::NotEnoughFaceVertices));
+    }
+    for i in 1..corners.len() - 1 {
+        facets.push(Facet {normal, corners: vec![corners[0], corners[i], corners[i + 1]]});
+    }
+    Ok(())
+}
+
+fn parse_vec3<'a>(parts: &mut SplitWhitespace<'a>, expected: &'static str) -> Result<Vec3, (&'a str, ObjParseErrorKind)> {
+    let mut values = [0.0; 3];
+    for value in values.iter_mut() {
+        let token = parts.next().ok_or(("", ObjParseErrorKind::MissingValue(expected)))?;
+        *value = token.parse::<f32>().map_err(|_| (token, ObjParseErrorKind::InvalidNumber))?;
+    }
+    if let Some(extra) = parts.next() {
+        return Err((extra, ObjParseErrorKind::UnexpectedValue("expected 3 components")));
+    }
+    Ok(Vec3::new(values[0], values[1], values[2]))
+}
+
+fn located_error(file_path: &str, statement: &Statement, token: &str, kind: ObjParseErrorKind) -> ObjParseError {
+    let (line_number, column) = statement.locate(token);
+    ObjParseError::new(file_path, line_number, column, token, kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{write_test_file, LENIENT, STRICT};
+
+    // normal then the three corners of each triangle
+    const TRIANGLES: [[f32; 12]; 2] = [
+        [0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0],
+    ];
+
+    fn binary_stl(header: &[u8], count: u32, triangles: &[[f32; 12]]) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        bytes.resize(80, b' ');
+        bytes.extend(count.to_le_bytes());
+        for triangle in triangles {
+            triangle.iter().for_each(|value| bytes.extend(value.to_le_bytes()));
+            bytes.extend([0, 0]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn binary_files_whose_header_starts_with_solid() {
+        let path = write_test_file("binary-solid.stl", &binary_stl(b"solid exported as binary", 2, &TRIANGLES));
+        let (obj_data, warnings) = parse_stl_file(&path, &STRICT).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!((obj_data.vertices.len(), obj_data.num_indices), (6, 6));
+        // the given normal is normalized, the missing one is left to be generated
+        assert_eq!(obj_data.vertices[0].normal.z, 1.0);
+        assert_eq!(obj_data.vertices[3].normal.length(), 0.0);
+        assert_eq!(obj_data.vertices[4].position.y, 1.0);
+    }
+
+    #[test]
+    fn binary_triangle_count_must_match_the_size() {
+        for count in [3, u32::MAX] {
+            let path = write_test_file("binary-count.stl", &binary_stl(b"", count, &TRIANGLES));
+            let error = parse_stl_file(&path, &STRICT).err().unwrap();
+            assert!(matches!(error.kind, ObjParseErrorKind::Malformed(_)));
+            // the triangles that are there are kept
+            let (obj_data, warnings) = parse_stl_file(&path, &LENIENT).unwrap();
+            assert_eq!((obj_data.num_indices, warnings.len()), (6, 1));
+        }
+        // a trailing partial triangle doesn't count
+        let mut bytes = binary_stl(b"", 2, &TRIANGLES);
+        bytes.extend([0; 20]);
+        let path = write_test_file("binary-trailing.stl", &bytes);
+        assert!(parse_stl_file(&path, &STRICT).is_err());
+    }
+
+    const ASCII: &str = "solid first\n facet normal 0 0 1\n  outer loop\n   vertex 0 0 0\n   vertex 1 0 0\n   vertex 1 1 0\n   vertex 0 1 0\n  endloop\n endfacet\nendsolid first\n\
+        solid second part\n facet normal 0 0 1\n  outer loop\n   vertex 0 0 1\n   vertex 1 0 z\n   vertex 1 1 1\n  endloop\n endfacet\n\
+        facet normal 0 0 1\n  outer loop\n   vertex 0 0 2\n   vertex 1 0 2\n   vertex 1 1 2\n  endloop\n endfacet\nendsolid\n";
+
+    #[test]
+    fn ascii_solids_and_facets() {
+        let path = write_test_file("ascii.stl", ASCII.as_bytes());
+        let error = parse_stl_file(&path, &STRICT).err().unwrap();
+        assert_eq!((error.line_number, error.column, error.kind), (15, 15, ObjParseErrorKind::InvalidNumber));
+
+        let (obj_data, warnings) = parse_stl_file(&path, &LENIENT).unwrap();
+        assert_eq!(warnings.len(), 1);
+        // the quad facet is split in two, the broken facet is dropped as a whole
+        assert_eq!(obj_data.num_indices, 3 * 3);
+        assert_eq!(obj_data.vertices[8].position.z, 2.0);
+        let sub_meshes: Vec<_> = obj_data.sub_meshes.iter()
+            .map(|sub_mesh| (sub_mesh.name(), sub_mesh.first_index, sub_mesh.num_indices))
+            .collect();
+        assert_eq!(sub_meshes, [("first".to_string(), 0, 6), ("second part".to_string(), 6, 3)]);
+    }
+
+    #[test]
+    fn unfinished_ascii_facets() {
+        let path = write_test_file("ascii-unfinished.stl", b"solid\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\n");
+        let error = parse_stl_file(&path, &STRICT).err().unwrap();
+        assert_eq!((error.line_number, error.kind), (4, ObjParseErrorKind::Malformed("the last facet has no endfacet")));
+        let path = write_test_file("ascii-nested.stl", b"solid\nfacet normal 0 0 1\nfacet normal 0 0 1\n");
+        assert!(parse_stl_file(&path, &STRICT).is_err());
+    }
+}
@@ -0,0 +1,20 @@
+use std::path::PathBuf;
+
+use crate::models::parse_options::{ParseOptions, Strictness};
+
+pub const LENIENT: ParseOptions = ParseOptions {strictness: Strictness::Lenient, resolution: 8};
+pub const STRICT: ParseOptions = ParseOptions {strictness: Strictness::Strict, resolution: 8};
+
+// a directory of its own for every test run, so parallel runs don't share files
+pub fn test_path(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("scop-test-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    directory.join(name)
+}
+
+// the parsers read from a path, so every test input goes through a file named after its test
+pub fn write_test_file(name: &str, contents: &[u8]) -> String {
+    let path = test_path(name);
+    std::fs::write(&path, contents).unwrap();
+    path.to_string_lossy().into_owned()
+}
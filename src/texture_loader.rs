@@ -1,9 +1,14 @@
-use std::fs::File;
-use std::io::{Read, Seek, SeekFrom};
+use std::fs;
+
+use crate::models::material::Material;
 
 pub fn load_texture(path: &str) -> Result<u32, String> {
-    let mut file = File::open(path).map_err(|e| format!("Error opening file: {}", e))?;
-    let data = read_bmp(&mut file)?;
+    let bytes = fs::read(path).map_err(|e| format!("Error opening file: {}", e))?;
+    load_texture_from_memory(&bytes)
+}
+
+pub fn load_texture_from_memory(bytes: &[u8]) -> Result<u32, String> {
+    let (data, width, height) = read_bmp(bytes)?;
     let mut texture_id = 0;
     unsafe {
         gl::GenTextures(1, &mut texture_id);
@@ -15,8 +20,8 @@ pub fn load_texture(path: &str) -> Result<u32, String> {
         gl::TexImage2D(gl::TEXTURE_2D,
             0,
             gl::RGB as i32,
-            512,
-            512,
+            width as i32,
+            height as i32,
             0,
             gl::RGB,
             gl::UNSIGNED_BYTE,
@@ -26,29 +31,40 @@ pub fn load_texture(path: &str) -> Result<u32, String> {
     Ok(texture_id)
 }
 
-fn read_bmp(file: &mut File) -> Result<Vec<u8>, String> {
-    let mut header = [0; 54];
-    file.read_exact(&mut header).map_err(|_| "Error reading header")?;
+// only bmp textures can be loaded, materials using anything else keep the texture given on the command line
+pub fn load_material_textures(materials: &mut [Material]) {
+    for material in materials.iter_mut() {
+        let (source, texture) = match (&material.diffuse_image, &material.diffuse_map) {
+            (Some(image), _) => ("(embedded image)", load_texture_from_memory(image)),
+            (None, Some(path)) => (path.as_str(), load_texture(path)),
+            (None, None) => continue,
+        };
+        match texture {
+            Ok(texture_id) => material.diffuse_texture_id = Some(texture_id),
+            Err(err) => println!("Warning: could not load texture {} for material {}: {}", source, material.name, err),
+        }
+    }
+}
+
+fn read_bmp(bytes: &[u8]) -> Result<(Vec<u8>, usize, usize), String> {
+    let header = bytes.get(..54).ok_or("Error reading header")?;
     
     if &header[0..2] != b"BM" {
         return Err("Not a BMP file".to_string());
     }
     
-    let data_pos = u32::from_le_bytes([header[0x0A], header[0x0B], header[0x0C], header[0x0D]]) as u64;
+    let data_pos = u32::from_le_bytes([header[0x0A], header[0x0B], header[0x0C], header[0x0D]]) as usize;
     let image_size = u32::from_le_bytes([header[0x22], header[0x23], header[0x24], header[0x25]]) as usize;
     let width = i32::from_le_bytes([header[0x12], header[0x13], header[0x14], header[0x15]]);
     let height = i32::from_le_bytes([header[0x16], header[0x17], header[0x18], header[0x19]]);
     
-    let tex_width = width.abs() as usize;
-    let tex_height = height.abs() as usize;
-
-    let image_size = if image_size == 0 { (tex_width * tex_height * 3) as usize } else { image_size };
-    let data_pos = if data_pos == 0 { 54 } else { data_pos as u64 };
+    let tex_width = width.unsigned_abs() as usize;
+    let tex_height = height.unsigned_abs() as usize;
 
-    file.seek(SeekFrom::Start(data_pos)).map_err(|_| "Error seeking to data")?;
+    let image_size = if image_size == 0 { tex_width * tex_height * 3 } else { image_size };
+    let data_pos = if data_pos == 0 { 54 } else { data_pos };
 
-    let mut data = vec![0; image_size];
-    file.read_exact(&mut data).map_err(|_| "Error reading data")?;
+    let data = data_pos.checked_add(image_size).and_then(|end| bytes.get(data_pos..end)).ok_or("Error reading data")?.to_vec();
     
-    Ok(data)
+    Ok((data, tex_width, tex_height))
 }
\ No newline at end of file
@@ -0,0 +1,172 @@
+use crate::models::vec3::Vec3;
+
+// splits a polygon into triangles given as indices into `points`, keeping the polygon winding.
+// the polygon is projected onto its best-fit plane then cut with ear clipping, so concave and
+// slightly non-planar faces come out right. degenerate or self-intersecting input falls back to a fan.
+pub fn triangulate(points: &[Vec3]) -> Vec<[usize; 3]> {
+    let n = points.len();
+    if n < 3 {
+        return Vec::new();
+    }
+    if n == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    // the normal and the ear areas grow with the square of the polygon size, so does what counts as zero
+    let extent = bounding_extent(points);
+    let epsilon = f32::EPSILON * extent * extent;
+    let normal = newell_normal(points);
+    if normal.length() <= epsilon {
+        return fan(&(0..n).collect::<Vec<usize>>());
+    }
+    let normal = normal.normalize();
+    let (u_axis, v_axis) = plane_basis(normal);
+    let projected: Vec<(f32, f32)> = points.iter()
+        .map(|p| (p.dot(u_axis), p.dot(v_axis)))
+        .collect();
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = Vec::with_capacity(n - 2);
+
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let prev = remaining[(i + count - 1) % count];
+            let cur = remaining[i];
+            let next = remaining[(i + 1) % count];
+            is_ear(&projected, &remaining, prev, cur, next, epsilon)
+        });
+        match ear {
+            Some(i) => {
+                let prev = remaining[(i + count - 1) % count];
+                let next = remaining[(i + 1) % count];
+                triangles.push([prev, remaining[i], next]);
+                remaining.remove(i);
+            }
+            // no ear left means the outline crosses itself or collapsed, finish with a fan
+            None => {
+                triangles.extend(fan(&remaining));
+                return triangles;
+            }
+        }
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+fn fan(polygon: &[usize]) -> Vec<[usize; 3]> {
+    (2..polygon.len())
+        .map(|i| [polygon[0], polygon[i - 1], polygon[i]])
+        .collect()
+}
+
+// Newell's method gives a stable normal even for non-planar or partly collinear polygons,
+// oriented along the polygon winding
+fn newell_normal(points: &[Vec3]) -> Vec3 {
+    let mut normal = Vec3::new(0.0, 0.0, 0.0);
+    for i in 0..points.len() {
+        let cur = points[i];
+        let next = points[(i + 1) % points.len()];
+        normal.x += (cur.y - next.y) * (cur.z + next.z);
+        normal.y += (cur.z - next.z) * (cur.x + next.x);
+        normal.z += (cur.x - next.x) * (cur.y + next.y);
+    }
+    normal
+}
+
+// longest side of the axis-aligned box around the points
+fn bounding_extent(points: &[Vec3]) -> f32 {
+    let mut min = points[0];
+    let mut max = points[0];
+    for p in points {
+        min = Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+        max = Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+    }
+    (max.x - min.x).max(max.y - min.y).max(max.z - min.z)
+}
+
+// two axes spanning the plane, chosen so the projected polygon winds counter-clockwise
+fn plane_basis(normal: Vec3) -> (Vec3, Vec3) {
+    let helper = if normal.x.abs() < 0.9 { Vec3::new(1.0, 0.0, 0.0) } else { Vec3::new(0.0, 1.0, 0.0) };
+    let u_axis = helper.cross(normal).normalize();
+    let v_axis = normal.cross(u_axis);
+    (u_axis, v_axis)
+}
+
+fn cross_2d(a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+fn is_ear(projected: &[(f32, f32)], remaining: &[usize], prev: usize, cur: usize, next: usize, epsilon: f32) -> bool {
+    let (a, b, c) = (projected[prev], projected[cur], projected[next]);
+    if cross_2d(a, b, c) <= epsilon {
+        return false;
+    }
+    remaining.iter()
+        .filter(|&&i| i != prev && i != cur && i != next)
+        .map(|&i| projected[i])
+        // duplicated positions sharing a corner with the ear don't block it
+        .filter(|&p| p != a && p != b && p != c)
+        .all(|p| !point_in_triangle(p, a, b, c))
+}
+
+// boundary points count as inside so that ears never touch the rest of the outline
+fn point_in_triangle(p: (f32, f32), a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> bool {
+    cross_2d(a, b, p) >= 0.0 && cross_2d(b, c, p) >= 0.0 && cross_2d(c, a, p) >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon(points: &[(f32, f32)], scale: f32) -> Vec<Vec3> {
+        points.iter().map(|&(x, y)| Vec3::new(x * scale, y * scale, 0.0)).collect()
+    }
+
+    // areas along +z, a triangle wound the wrong way comes out negative
+    fn areas(points: &[Vec3], triangles: &[[usize; 3]]) -> Vec<f32> {
+        triangles.iter()
+            .map(|&[a, b, c]| points[b].sub(points[a]).cross(points[c].sub(points[a])).z / 2.0)
+            .collect()
+    }
+
+    // an arrow head pointing right, the notch at (1, 1) is the only reflex corner
+    const ARROW: [(f32, f32); 5] = [(0.0, 0.0), (3.0, 1.0), (0.0, 2.0), (1.0, 1.0), (0.0, 0.5)];
+
+    #[test]
+    fn concave_polygons_stay_inside_their_outline() {
+        let points = polygon(&ARROW, 1.0);
+        let triangles = triangulate(&points);
+        assert_eq!(triangles.len(), 3);
+        let areas = areas(&points, &triangles);
+        assert!(areas.iter().all(|&area| area > 0.0), "{:?}", areas);
+        // a fan from the first corner would cover the notch and add to the area
+        assert!((areas.iter().sum::<f32>() - 2.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn collinear_corners_make_no_empty_triangles() {
+        let points = polygon(&[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (2.0, 2.0), (1.0, 2.0), (0.0, 2.0)], 1.0);
+        let triangles = triangulate(&points);
+        assert_eq!(triangles.len(), 4);
+        let areas = areas(&points, &triangles);
+        assert!(areas.iter().all(|&area| area > 0.0), "{:?}", areas);
+        assert!((areas.iter().sum::<f32>() - 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn small_and_large_polygons_are_cut_the_same_way() {
+        let reference = triangulate(&polygon(&ARROW, 1.0));
+        for scale in [1e-4, 1e4] {
+            assert_eq!(triangulate(&polygon(&ARROW, scale)), reference, "{}", scale);
+        }
+    }
+
+    #[test]
+    fn winding_is_kept() {
+        let mut reversed = ARROW;
+        reversed.reverse();
+        let points = polygon(&reversed, 1.0);
+        assert!(areas(&points, &triangulate(&points)).iter().all(|&area| area < 0.0));
+    }
+}